@@ -0,0 +1,118 @@
+//! The editor module. The `Editor` holds the state of the interactive prompt: the typed input, its
+//! suggestions and the highlighted suggestion. It handles the keys, the terminal is drawn by `main`.
+use completion::{AutoCompletor, Suggestion};
+use crossterm::event::KeyCode;
+
+/// What to do after a key is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The input or the highlighted suggestion changed
+    Redraw,
+    /// Nothing changed
+    Ignore,
+    Quit,
+}
+
+/// The input and the suggestions of the prompt
+#[derive(Debug, Default)]
+pub struct Editor {
+    characters: Vec<char>,
+    suggestions: Vec<Suggestion>,
+    /// The highlighted suggestion, selected with the arrow keys
+    selected: Option<usize>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor::default()
+    }
+
+    pub fn prefix(&self) -> String {
+        self.characters.iter().collect()
+    }
+
+    pub fn suggestions(&self) -> &[Suggestion] {
+        &self.suggestions
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Handles the key: a character is typed, Backspace deletes the last one, Tab accepts the
+    /// highlighted suggestion (the top one if none is), Up/Down cycle through the suggestions and
+    /// any other key quits. The suggestions are refreshed from the `completor` when the input changes.
+    pub fn handle(&mut self, code: KeyCode, completor: &dyn AutoCompletor) -> Action {
+        match code {
+            KeyCode::Char(ch) => self.characters.push(ch),
+            KeyCode::Backspace => {
+                if self.characters.pop().is_none() {
+                    return Action::Ignore;
+                }
+            }
+            KeyCode::Tab => match self.suggestions.get(self.selected.unwrap_or(0)) {
+                Some(suggestion) => self.characters = suggestion.word.chars().collect(),
+                None => return Action::Ignore,
+            },
+            KeyCode::Down | KeyCode::Up if self.suggestions.is_empty() => return Action::Ignore,
+            KeyCode::Down => {
+                let len = self.suggestions.len();
+                self.selected = Some(self.selected.map_or(0, |i| (i + 1) % len));
+                return Action::Redraw;
+            }
+            KeyCode::Up => {
+                let len = self.suggestions.len();
+                self.selected = Some(self.selected.map_or(len - 1, |i| (i + len - 1) % len));
+                return Action::Redraw;
+            }
+            _ => return Action::Quit,
+        }
+        self.selected = None;
+        self.suggestions = completor.suggestions(&self.prefix());
+        Action::Redraw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Editor};
+    use completion::trie::Trie;
+    use crossterm::event::KeyCode;
+
+    fn words(editor: &Editor) -> Vec<&str> {
+        editor.suggestions().iter().map(|s| &s.word[..]).collect()
+    }
+
+    #[test]
+    fn editor_test() {
+        let trie = Trie::new(&[("car", 1), ("carpet", 2), ("cat", 3)]);
+        let mut editor = Editor::new();
+        assert_eq!(editor.handle(KeyCode::Backspace, &trie), Action::Ignore);
+        assert_eq!(editor.handle(KeyCode::Down, &trie), Action::Ignore);
+        assert_eq!(editor.handle(KeyCode::Char('c'), &trie), Action::Redraw);
+        assert_eq!(editor.handle(KeyCode::Char('a'), &trie), Action::Redraw);
+        assert_eq!(words(&editor), vec!["cat", "carpet", "car"]);
+        // the highlighted suggestion cycles, the suggestions don't change
+        assert_eq!(editor.handle(KeyCode::Up, &trie), Action::Redraw);
+        assert_eq!(editor.selected(), Some(2));
+        editor.handle(KeyCode::Down, &trie);
+        editor.handle(KeyCode::Down, &trie);
+        assert_eq!(editor.selected(), Some(1));
+        assert_eq!(words(&editor), vec!["cat", "carpet", "car"]);
+        assert_eq!(editor.handle(KeyCode::Tab, &trie), Action::Redraw);
+        assert_eq!((editor.prefix(), editor.selected()), ("carpet".to_string(), None));
+        assert_eq!(words(&editor), vec!["carpet"]);
+        editor.handle(KeyCode::Backspace, &trie);
+        editor.handle(KeyCode::Backspace, &trie);
+        editor.handle(KeyCode::Backspace, &trie);
+        assert_eq!(editor.prefix(), "car");
+        assert_eq!(words(&editor), vec!["carpet", "car"]);
+        // the top suggestion is accepted when none is highlighted
+        editor.handle(KeyCode::Char('x'), &trie);
+        assert_eq!(editor.handle(KeyCode::Tab, &trie), Action::Ignore);
+        editor.handle(KeyCode::Backspace, &trie);
+        editor.handle(KeyCode::Tab, &trie);
+        assert_eq!(editor.prefix(), "carpet");
+        assert_eq!(editor.handle(KeyCode::Esc, &trie), Action::Quit);
+    }
+}
@@ -0,0 +1,136 @@
+//! A typed client for the completion server (see the `server` crate).
+//! The client keeps the connections alive between requests, so it should be created once and reused
+//! (it is cheap to clone and can be shared across threads).
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder};
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A suggestion returned by the completion server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub word: String,
+    pub score: u32,
+}
+
+/// Builds a `CompletionClient`, e.g.
+/// `ClientBuilder::new("http://localhost:8080").timeout(Duration::from_millis(200)).build()`
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    base_url: String,
+    connect_timeout: Duration,
+    timeout: Duration,
+}
+
+impl ClientBuilder {
+    /// The `base_url` is the address of the server, e.g. "http://localhost:8080"
+    pub fn new(base_url: &str) -> Self {
+        ClientBuilder {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// The time allowed to establish a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// The time allowed for a whole request (including the connection)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> CompletionClient {
+        let agent = AgentBuilder::new()
+            .timeout_connect(self.connect_timeout)
+            .timeout(self.timeout)
+            .build();
+        CompletionClient {
+            agent,
+            complete_url: format!("{}/complete", self.base_url),
+        }
+    }
+}
+
+/// The client of the completion server.
+/// The errors are `io::Error`s, a timeout is reported as `ErrorKind::TimedOut` and an invalid
+/// request or response as `ErrorKind::InvalidData`.
+#[derive(Debug, Clone)]
+pub struct CompletionClient {
+    agent: Agent,
+    complete_url: String,
+}
+
+impl CompletionClient {
+    /// Creates a client with the default timeouts, see `ClientBuilder`
+    pub fn new(base_url: &str) -> Self {
+        ClientBuilder::new(base_url).build()
+    }
+
+    /// Returns (at most) `k` suggestions for the `prefix`, highest score first
+    pub fn complete(&self, prefix: &str, k: usize) -> io::Result<Vec<Suggestion>> {
+        let response = self
+            .agent
+            .get(&self.complete_url)
+            .query("q", prefix)
+            .query("k", &k.to_string())
+            .call()
+            .map_err(to_io_error)?;
+        let body: Value = serde_json::from_reader(response.into_reader())?;
+        parse_suggestions(&body)
+    }
+}
+
+fn parse_suggestions(body: &Value) -> io::Result<Vec<Suggestion>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid response {}", body));
+    body["suggestions"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|suggestion| {
+            let word = suggestion["word"].as_str().ok_or_else(invalid)?;
+            let score = suggestion["score"].as_u64().ok_or_else(invalid)?;
+            Ok(Suggestion {
+                word: word.to_string(),
+                score: u32::try_from(score).map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+fn to_io_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            // the server explains the rejection in the body, e.g. {"error":"missing parameter q"}
+            let message = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+                .and_then(|body| body["error"].as_str().map(|e| e.to_string()))
+                .unwrap_or_default();
+            let kind = if status == 400 {
+                io::ErrorKind::InvalidData
+            } else {
+                io::ErrorKind::Other
+            };
+            io::Error::new(kind, format!("server returned {}: {}", status, message))
+        }
+        ureq::Error::Transport(transport) => {
+            let kind = match transport.source().and_then(|e| e.downcast_ref::<io::Error>()) {
+                Some(e) if e.kind() == io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut,
+                Some(e) => e.kind(),
+                None => io::ErrorKind::Other,
+            };
+            io::Error::new(kind, transport)
+        }
+    }
+}
@@ -1,44 +1,36 @@
 use crossterm::style::style;
-use completion::{AutoCompletor, trie::Trie};
+use completion::loader::{Format, LoadError, Loader};
+use completion::{AutoCompletor, Suggestion, trie::Trie};
 use crossterm::{style::Color, cursor};
-use crossterm::event::{self, KeyEvent};
-use crossterm::event::{read, Event, KeyCode};
+use crossterm::event::{read, Event};
 use crossterm::style::{Attribute};
 use crossterm::terminal::enable_raw_mode;
 use crossterm::{
     style::{self},
-    terminal, ExecutableCommand, QueueableCommand, Result,
+    terminal, QueueableCommand, Result,
 };
 use indicatif::{ProgressBar, ProgressStyle};
 use std::{time::Instant, env, io::stdout, process::exit};
-use std::io::BufRead;
 use std::io::Stdout;
 use std::io::Write;
-use std::{fs::File, io::BufReader};
 use terminal::disable_raw_mode;
 
 use completion::naive::NaiveAutoComplete;
+use editor::{Action, Editor};
 
-static FILE_NAME: & 'static str = "./all_words.txt";
+mod editor;
 
-pub fn read_char() -> Result<char> {
-    loop {
-        if let Event::Key(KeyEvent {
-            code: KeyCode::Char(c),
-            ..
-        }) = event::read()?
-        {
-            return Ok(c);
-        }
-    }
-}
+static FILE_NAME: &str = "./all_words.txt";
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.is_empty() {
-        println!("Usage: client <trie|naive>");
+    if args.len() < 2 || args.len() > 3 {
+        println!("Usage: client <trie|naive> [dictionary]");
+        println!("The dictionary has one <word> per line, or one <word>\\t<score> per line if it is a .tsv file");
+        println!("(default: {})", FILE_NAME);
         exit(-1);
     }
+    let file_name = args.get(2).map_or(FILE_NAME, |f| &f[..]);
     let mut stdout = stdout();
     let auto_complete_type = &args[1];
     if !["trie", "naive"].contains(&&auto_complete_type[..]) {
@@ -72,8 +64,14 @@ fn main() -> Result<()> {
             .template("{spinner:.blue} {msg}"),
     );
     let s1 = Instant::now();
-    pb.set_message(&format!("Reading suggestions from [{}]...", FILE_NAME));
-    let input = input();
+    pb.set_message(&format!("Reading suggestions from [{}]...", file_name));
+    let input = match input(file_name) {
+        Ok(input) => input,
+        Err(e) => {
+            pb.finish_with_message(&format!("Could not read [{}]: {}", file_name, e));
+            exit(-1);
+        }
+    };
     let s2 = Instant::now();
     let inp = input
         .iter()
@@ -90,78 +88,42 @@ fn main() -> Result<()> {
     // println!("Initialized auto_completor({}) in {} ms", auto_complete_type, start_time.elapsed().as_millis());
     enable_raw_mode()?;
     stdout
-        .queue(style::Print("Enter your input (press Esc to quit): "))?
-        .queue(cursor::SavePosition)?
+        .queue(style::Print("Enter your input (Tab to accept, Up/Down to cycle, Esc to quit): "))?
         .flush()?;
-    let mut characters: Vec<char> = vec![];    
-    loop {
-        match read()? {
-            Event::Key(event) => {
-                match event.code {
-                    KeyCode::Char(ch) => {
-                        characters.push(ch);
-                        let prefix = characters.iter().collect::<String>();
-                        let suggestions = suggestions(&auto_completor, &prefix[..]);
-                        stdout
-                            .queue(cursor::RestorePosition)?
-                            .queue(style::Print(ch))?
-                            .queue(cursor::SavePosition)?
-                            .flush()?;
-
-                        stdout
-                            .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?
-                            .flush()?;
-                        print_suggestions(&mut stdout, &suggestions, &prefix[..])?;
-                        stdout.queue(cursor::RestorePosition)?.flush()?;
-                    }
-                    KeyCode::Backspace => {
-                        let popped_char = characters.pop();
-                        if popped_char.is_some() {
-                            let prefix = characters.iter().collect::<String>();
-                            let suggestions = suggestions(&auto_completor, &prefix[..]);
-                            stdout
-                                .queue(cursor::MoveLeft(1))?
-                                .queue(cursor::SavePosition)?
-                                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
-                                .flush()?;
-
-                            stdout
-                                .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?
-                                .flush()?;
-
-                            print_suggestions(&mut stdout, &suggestions, &prefix[..])?;
-                            stdout.queue(cursor::RestorePosition)?.flush()?;
-                        }
-                    }
-
-                    _ => break,
-                }
+    let start = cursor::position()?;
+    let mut editor = Editor::new();
+    while let Event::Key(event) = read()? {
+        match editor.handle(event.code, &*auto_completor) {
+            Action::Redraw => {
+                let prefix = editor.prefix();
+                redraw(&mut stdout, start, &prefix, editor.suggestions(), editor.selected())?;
             }
-            _ => break,
+            Action::Ignore => continue,
+            Action::Quit => break,
         }
     }
     disable_raw_mode()?;
+    stdout.queue(cursor::MoveToNextLine(1))?.flush()?;
     Ok(())
 }
 
-fn input() -> Vec<(String, u32)> {
-    let file = File::open(FILE_NAME).unwrap();
-    let reader = BufReader::new(file);
-    reader
-        .lines()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap())
-        .map(|r| (r.clone(), r.len() as u32))
-        .collect::<Vec<(String, u32)>>()
-}
-
-fn suggestions(auto_completor: &Box< dyn AutoCompletor>, prefix: &str) -> Vec<String> {
-    let c = auto_completor
-        .suggestions(prefix)
-        .iter()
-        .map(|s| (*s.word).clone())
-        .collect::<Vec<String>>();
-    c
+/// Reads the dictionary (gzipped if it ends with .gz), a .tsv file has a score after the last tab
+/// of every line, the words of any other file are scored by their length.
+/// The empty lines are skipped.
+fn input(file_name: &str) -> std::result::Result<Vec<(String, u32)>, LoadError> {
+    let tsv = file_name.trim_end_matches(".gz").ends_with(".tsv");
+    if tsv {
+        return Loader::new(Format::Tsv).score_last().load_file(file_name);
+    }
+    let input = Loader::new(Format::Lines)
+        .load_file(file_name)?
+        .into_iter()
+        .map(|(word, _)| {
+            let score = word.len() as u32;
+            (word, score)
+        })
+        .collect();
+    Ok(input)
 }
 
 fn auto_completor_factory(auto_complete_type: &str, inp: Vec<(&str, u32)>) -> Box<dyn AutoCompletor> {
@@ -172,29 +134,43 @@ fn auto_completor_factory(auto_complete_type: &str, inp: Vec<(&str, u32)>) -> Bo
     }
 }
 
-fn print_suggestions<'a>(
-    stdout: &'a mut Stdout,
-    suggestions: &[String],
+/// Redraws the input (starting at `start`) followed by the suggestions and their scores,
+/// the cursor is left at the end of the input.
+fn redraw(
+    stdout: &mut Stdout,
+    start: (u16, u16),
     prefix: &str,
-) -> Result<&'a mut Stdout> {
-    stdout.execute(cursor::MoveToNextLine(0))?;
+    suggestions: &[Suggestion],
+    selected: Option<usize>,
+) -> Result<()> {
+    stdout
+        .queue(cursor::MoveTo(start.0, start.1))?
+        .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?
+        .queue(style::Print(prefix))?
+        .queue(cursor::SavePosition)?
+        .queue(cursor::MoveToNextLine(1))?;
     if suggestions.is_empty() {
-        stdout.execute(style::Print("No suggestions"))?;
+        stdout.queue(style::Print("No suggestions"))?;
     }
-    for s in suggestions {
-        let suffix = s.strip_prefix(prefix).unwrap();
+    for (i, s) in suggestions.iter().enumerate() {
+        let suffix = s.word.strip_prefix(prefix).unwrap_or(&s.word[..]);
+        let matched = &s.word[..s.word.len() - suffix.len()];
+        if selected == Some(i) {
+            stdout.queue(style::SetAttribute(Attribute::Reverse))?;
+        }
         stdout
             .queue(style::Print(format!(
-                "{}{}{}{}{}{}",
+                "{}{}{}{}{}{} ({})",
                 Attribute::Underlined,
-                prefix,
+                matched,
                 Attribute::NoUnderline,
                 Attribute::Bold,
                 suffix,
-                Attribute::Reset
+                Attribute::Reset,
+                s.score()
             )))?
-            .queue(cursor::MoveToNextLine(0))?;
+            .queue(cursor::MoveToNextLine(1))?;
     }
-    stdout.flush()?;
-    Ok(stdout)
+    stdout.queue(cursor::RestorePosition)?.flush()?;
+    Ok(())
 }
@@ -0,0 +1,129 @@
+use client::{ClientBuilder, CompletionClient, Suggestion};
+use completion::trie::Trie;
+use server::CompletionServer;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn start_server() -> String {
+    let trie = Trie::new(&[
+        ("car", 1),
+        ("carpet", 2),
+        ("carpenter", 3),
+        ("cocoon", 5),
+        ("café au lait", 4),
+    ]);
+    let server = CompletionServer::bind("127.0.0.1:0", Arc::new(trie)).unwrap();
+    let addr = server.local_addr().unwrap();
+    server.spawn();
+    format!("http://{}/", addr)
+}
+
+/// Starts a proxy in front of the server (at `base_url`), which counts the accepted connections
+fn start_counting_proxy(base_url: &str) -> (String, Arc<AtomicUsize>) {
+    let server = base_url.trim_start_matches("http://").trim_end_matches('/').to_string();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut client = client.unwrap();
+            let mut upstream = TcpStream::connect(&server).unwrap();
+            let (mut client_reader, mut upstream_writer) =
+                (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+            thread::spawn(move || io::copy(&mut client_reader, &mut upstream_writer));
+            thread::spawn(move || io::copy(&mut upstream, &mut client));
+        }
+    });
+    (format!("http://{}", addr), accepted)
+}
+
+fn suggestion(word: &str, score: u32) -> Suggestion {
+    Suggestion {
+        word: word.to_string(),
+        score,
+    }
+}
+
+#[test]
+fn complete_test() {
+    let client = CompletionClient::new(&start_server());
+    assert_eq!(
+        client.complete("car", 2).unwrap(),
+        vec![suggestion("carpenter", 3), suggestion("carpet", 2)]
+    );
+    assert_eq!(client.complete("café a", 5).unwrap(), vec![suggestion("café au lait", 4)]);
+    assert_eq!(client.complete("c", 0).unwrap(), vec![]);
+    assert_eq!(client.complete("xyz", 5).unwrap(), vec![]);
+    let error = client.complete("c", 1000).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(error.to_string().contains("400"), "{}", error);
+}
+
+#[test]
+fn shared_client_test() {
+    let client = CompletionClient::new(&start_server());
+    let handles = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    assert_eq!(client.complete("co", 1).unwrap(), vec![suggestion("cocoon", 5)]);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+}
+
+#[test]
+fn keep_alive_test() {
+    let (proxy, accepted) = start_counting_proxy(&start_server());
+    let client = CompletionClient::new(&proxy);
+    for _ in 0..5 {
+        assert_eq!(client.complete("co", 1).unwrap(), vec![suggestion("cocoon", 5)]);
+    }
+    // the requests reuse one connection
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn invalid_score_test() {
+    // answers with a score larger than u32::MAX
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // the request ends with an empty line
+        let request = BufReader::new(stream.try_clone().unwrap());
+        request.lines().map(|line| line.unwrap()).find(|line| line.is_empty());
+        let body = r#"{"prefix":"car","suggestions":[{"word":"car","score":4294967296}]}"#;
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+    });
+    let client = CompletionClient::new(&format!("http://{}", addr));
+    let error = client.complete("car", 5).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", error);
+}
+
+#[test]
+fn timeout_test() {
+    // accepts the connection but never responds
+    let _listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = _listener.local_addr().unwrap();
+    let client = ClientBuilder::new(&format!("http://{}", addr))
+        .timeout(Duration::from_millis(200))
+        .build();
+    let error = client.complete("car", 5).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TimedOut, "{}", error);
+}
@@ -1,18 +1,18 @@
+use completion::loader::{Format, Loader};
 use completion::{naive::NaiveAutoComplete, trie::Trie};
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use io::BufReader;
-use std::io::{self, BufRead};
-use std::{fs::File, time::Instant};
+use std::time::Instant;
 
+/// The words are scored by their length
 fn input() -> Vec<(String, u32)> {
-    let file =
-        File::open("../100_000_words.txt").unwrap();
-    let reader = BufReader::new(file);
-    reader
-        .lines()
-        .filter(|r| r.is_ok())
-        .map(|r| r.unwrap())
-        .map(|r| (r.clone(), r.len() as u32))
+    Loader::new(Format::Lines)
+        .load_file("../100_000_words.txt")
+        .unwrap()
+        .into_iter()
+        .map(|(word, _)| {
+            let score = word.len() as u32;
+            (word, score)
+        })
         .collect::<Vec<(String, u32)>>()
 }
 
@@ -27,7 +27,7 @@ fn validate_outputs(prefixes: &[&str], trie: &Trie, naive: &NaiveAutoComplete) {
     prefixes
         .iter()
         .for_each(|p|{
-            assert_eq!(trie.suggestions(*p), naive.suggestions(*p));
+            assert_eq!(trie.suggestions(p), naive.suggestions(p));
         });
     println!("Validated that the outputs match!");
 }
@@ -0,0 +1,401 @@
+//! The decay module.
+//! A `DecayingTrie` ranks its words by a score that decays over time, `base * exp(-λ·age)`,
+//! evaluated at query time, so yesterday's hot query falls behind today's without a rebuild.
+//! The decay of all the words is the same, so their order does not change over time: a word is
+//! stored (and ranked) by its forward decayed score, its log at a fixed epoch
+//! `ln(base) + λ·(at - epoch)`, and the cached top suggestions of the trie stay valid.
+
+use crate::trie::{Trie, TrieBuilder};
+use crate::{AutoCompletor, Suggestion};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The (log) scores are ranked with this precision, i.e. the scores within 0.01% of each other
+/// rank by their words
+const PRECISION: f64 = 10_000.0;
+
+/// Added to the (log) scores when they are ranked, so the scores below 1 at the epoch still rank
+/// by their values (down to `exp(-OFFSET)`, below the smallest positive `f64`)
+const OFFSET: f64 = 1_000.0;
+
+/// The shortest half life of a `DecayingTrie`
+const MIN_HALF_LIFE: Duration = Duration::from_millis(1);
+
+/// Completes the prefixes with the words ranked by their decayed scores
+#[derive(Debug)]
+pub struct DecayingTrie<P = ()> {
+    /// The words and their payloads, the score of a word ranks its forward decayed score
+    index: Trie<P>,
+    /// The forward decayed (log) score of every word, by its key
+    logs: HashMap<Vec<char>, f64>,
+    half_life: Duration,
+    epoch: Instant,
+}
+
+impl DecayingTrie {
+    /// Initializes the trie from a given list of suggestions and (current) scores,
+    /// the scores halve every `half_life` (at least a millisecond)
+    pub fn new(input: &[(&str, u32)], half_life: Duration) -> Self {
+        DecayingTrie::with_builder(TrieBuilder::new(), input, half_life)
+    }
+
+    /// Initializes the trie with the configuration and the normalizer of the `builder`
+    pub fn with_builder(builder: TrieBuilder, input: &[(&str, u32)], half_life: Duration) -> Self {
+        let now = Instant::now();
+        let mut decaying = DecayingTrie::from(builder.build_empty(), half_life, now);
+        input.iter().for_each(|(word, score)| {
+            decaying.upsert_at(word, *score, now);
+        });
+        decaying
+    }
+}
+
+impl<P: Default> DecayingTrie<P> {
+    /// Inserts the given `word` with the `score` now, or replaces it if the word is present.
+    /// Returns the previous decayed score (if any).
+    pub fn upsert(&mut self, word: &str, score: u32) -> Option<u32> {
+        self.upsert_at(word, score, Instant::now())
+    }
+
+    /// Inserts the given `word` with the `score` at the instant `at` (the score decays from then
+    /// on), or replaces it if the word is present. Returns the previous decayed score (if any).
+    /// The payload of a present word is kept, a new word gets the default payload.
+    pub fn upsert_at(&mut self, word: &str, score: u32, at: Instant) -> Option<u32> {
+        let payload = self.payload(word);
+        self.replace(word, score, payload, at)
+    }
+
+    /// Adds the `weight` to the decayed score of the given `word` now (inserting the word if it is
+    /// not present), e.g. a hit of a query. The score is an exponentially weighted count of the
+    /// hits, the recent hits count more. Returns the updated decayed score.
+    pub fn hit(&mut self, word: &str, weight: u32) -> u32 {
+        self.hit_at(word, weight, Instant::now())
+    }
+
+    /// Adds the `weight` to the decayed score of the given `word` at the instant `at`
+    pub fn hit_at(&mut self, word: &str, weight: u32, at: Instant) -> u32 {
+        let key = self.index.key(word);
+        let hit = (weight as f64).ln() + self.elapsed(at);
+        let log = match self.logs.get(&key) {
+            Some(current) => log_add(*current, hit),
+            None => hit,
+        };
+        let payload = self.payload(word);
+        self.store(key, Suggestion::with_payload(Arc::new(word.into()), 0, payload), log, at);
+        self.score_at(word, at).unwrap_or(0)
+    }
+
+    /// Returns the payload of the given `word`, or the default one if it is not present
+    fn payload(&self, word: &str) -> Arc<P> {
+        match self.index.get(word) {
+            Some(current) => current.payload,
+            None => Arc::new(P::default()),
+        }
+    }
+}
+
+impl<P> DecayingTrie<P> {
+    fn from(index: Trie<P>, half_life: Duration, epoch: Instant) -> Self {
+        DecayingTrie {
+            index,
+            logs: HashMap::new(),
+            half_life: half_life.max(MIN_HALF_LIFE),
+            epoch,
+        }
+    }
+
+    /// Initializes the trie with the configuration and the normalizer of the `builder` from a
+    /// given list of suggestions, (current) scores and payloads
+    pub fn with_builder_and_payloads<'a>(
+        builder: TrieBuilder,
+        input: impl IntoIterator<Item = (&'a str, u32, P)>,
+        half_life: Duration,
+    ) -> Self {
+        let now = Instant::now();
+        let mut decaying = DecayingTrie::from(builder.build_empty(), half_life, now);
+        input.into_iter().for_each(|(word, score, payload)| {
+            decaying.upsert_with_payload_at(word, score, payload, now);
+        });
+        decaying
+    }
+
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    /// Returns the log of the decay between the epoch and the instant `at`, `λ·(at - epoch)`.
+    /// An instant before the epoch counts as the epoch.
+    fn elapsed(&self, at: Instant) -> f64 {
+        let age = at.saturating_duration_since(self.epoch).as_secs_f64();
+        age * std::f64::consts::LN_2 / self.half_life.as_secs_f64()
+    }
+
+    /// Returns the decayed score of the forward decayed `log` score at the instant `now`
+    fn value(&self, log: f64, now: Instant) -> u32 {
+        (log - self.elapsed(now)).exp().round() as u32
+    }
+
+    /// Stores the `suggestion` (its score is ignored) with the forward decayed `log` score at the
+    /// given key
+    fn store(&mut self, key: Vec<char>, suggestion: Suggestion<P>, log: f64, at: Instant) {
+        let mut log = log;
+        if (log + OFFSET) * PRECISION > u32::MAX as f64 {
+            log -= self.rebase(at);
+        }
+        self.index.upsert_at(&key, suggestion.with_score(rank(log)));
+        self.logs.insert(key, log);
+    }
+
+    /// Moves the epoch to the instant `at`, so the forward decayed scores stay small (it only
+    /// happens after many half lives). Returns the log of the decay between the two epochs.
+    fn rebase(&mut self, at: Instant) -> f64 {
+        let shift = self.elapsed(at);
+        self.epoch = self.epoch.max(at);
+        for (key, log) in self.logs.iter_mut() {
+            *log -= shift;
+            if let Some(current) = self.index.get_key(key) {
+                self.index.upsert_at(key, current.with_score(rank(*log)));
+            }
+        }
+        shift
+    }
+
+    /// Inserts the given `word` with the `score` and the `payload` at the instant `at` (the score
+    /// decays from then on), or replaces both if the word is present. Returns the previous decayed
+    /// score (if any).
+    pub fn upsert_with_payload_at(
+        &mut self,
+        word: &str,
+        score: u32,
+        payload: P,
+        at: Instant,
+    ) -> Option<u32> {
+        self.replace(word, score, Arc::new(payload), at)
+    }
+
+    fn replace(&mut self, word: &str, score: u32, payload: Arc<P>, at: Instant) -> Option<u32> {
+        let key = self.index.key(word);
+        let previous = self.logs.get(&key).map(|log| self.value(*log, at));
+        let log = (score as f64).ln() + self.elapsed(at);
+        self.store(key, Suggestion::with_payload(Arc::new(word.into()), 0, payload), log, at);
+        previous
+    }
+
+    /// Removes the given `word`, returns `false` if it is not present.
+    pub fn remove(&mut self, word: &str) -> bool {
+        self.logs.remove(&self.index.key(word));
+        self.index.remove(word).is_some()
+    }
+
+    /// Returns the decayed score of the given `word` now (if it is present)
+    pub fn score(&self, word: &str) -> Option<u32> {
+        self.score_at(word, Instant::now())
+    }
+
+    /// Returns the decayed score of the given `word` at the instant `now` (if it is present)
+    pub fn score_at(&self, word: &str, now: Instant) -> Option<u32> {
+        let log = self.logs.get(&self.index.key(word))?;
+        Some(self.value(*log, now))
+    }
+
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion<P>> {
+        self.suggestions_with_limit(prefix, self.index.config().max_suggestions)
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`, with their scores decayed to now
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion<P>> {
+        self.suggestions_at(prefix, limit, Instant::now())
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`, with their scores decayed to
+    /// the instant `now`
+    pub fn suggestions_at(&self, prefix: &str, limit: usize, now: Instant) -> Vec<Suggestion<P>> {
+        self.index
+            .top_suggestions(&self.index.key(prefix), limit)
+            .into_iter()
+            .map(|suggestion| {
+                let log = self.logs.get(&self.index.key(&suggestion.word));
+                let score = log.map_or(0, |log| self.value(*log, now));
+                suggestion.with_score(score)
+            })
+            .collect()
+    }
+}
+
+/// Returns the score that ranks the forward decayed `log` score,
+/// the scores below `exp(-OFFSET)` at the epoch rank the same
+fn rank(log: f64) -> u32 {
+    ((log + OFFSET) * PRECISION).round().max(0.0) as u32
+}
+
+/// Returns `ln(exp(a) + exp(b))`, without overflowing
+fn log_add(a: f64, b: f64) -> f64 {
+    let (max, min) = if a > b { (a, b) } else { (b, a) };
+    if min == f64::NEG_INFINITY {
+        return max;
+    }
+    max + (min - max).exp().ln_1p()
+}
+
+impl AutoCompletor for DecayingTrie {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions(prefix)
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rank, DecayingTrie};
+    use crate::naive::NaiveAutoComplete;
+    use crate::normalize::StandardNormalizer;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::TrieBuilder;
+    use std::time::{Duration, Instant};
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn words(trie: &DecayingTrie, prefix: &str, now: Instant) -> Vec<(String, u32)> {
+        trie.suggestions_at(prefix, 5, now)
+            .iter()
+            .map(|s| ((*s.word).clone(), s.score()))
+            .collect()
+    }
+
+    #[test]
+    fn decaying_suggestions_test() {
+        let mut trie = DecayingTrie::new(&[("election", 800), ("eclipse", 100)], HOUR);
+        let start = trie.epoch;
+        let owned = |words: &[(&str, u32)]| -> Vec<(String, u32)> {
+            words.iter().map(|(w, s)| (w.to_string(), *s)).collect()
+        };
+        assert_eq!(
+            words(&trie, "e", start),
+            owned(&[("election", 800), ("eclipse", 100)])
+        );
+        // the scores halve every hour, the order does not change
+        assert_eq!(
+            words(&trie, "e", start + HOUR * 2),
+            owned(&[("election", 200), ("eclipse", 25)])
+        );
+        // today's hot query overtakes yesterday's
+        assert_eq!(trie.upsert_at("earthquake", 300, start + HOUR * 2), None);
+        assert_eq!(
+            words(&trie, "e", start + HOUR * 3),
+            owned(&[("earthquake", 150), ("election", 100), ("eclipse", 13)])
+        );
+        assert_eq!(trie.upsert_at("eclipse", 50, start + HOUR * 3), Some(13));
+        assert_eq!(trie.score_at("eclipse", start + HOUR * 4), Some(25));
+        assert_eq!(words(&trie, "ecl", start + HOUR * 4), owned(&[("eclipse", 25)]));
+        assert!(trie.remove("election"));
+        assert!(!trie.remove("election"));
+        assert_eq!(trie.score_at("election", start), None);
+        assert_eq!(trie.suggestions_at("e", 1, start + HOUR * 4).len(), 1);
+    }
+
+    #[test]
+    fn decaying_hits_test() {
+        let builder = TrieBuilder::new()
+            .normalizer(StandardNormalizer::new().lowercase())
+            .max_suggestions(2);
+        let mut trie = DecayingTrie::with_builder(builder, &[], HOUR);
+        let start = trie.epoch;
+        // the hits add up, decayed to the time of each hit
+        assert_eq!(trie.hit_at("Paris", 10, start), 10);
+        assert_eq!(trie.hit_at("paris", 10, start + HOUR), 15);
+        assert_eq!(trie.hit_at("Pasta", 8, start + HOUR), 8);
+        assert_eq!(trie.hit_at("Pasta", 8, start + HOUR * 2), 12);
+        let ranked = |now: Instant| -> Vec<String> {
+            trie.suggestions_at("PA", 5, now).iter().map(|s| (*s.word).clone()).collect()
+        };
+        // paris was 7.5 and pasta 12 two hours after the start
+        assert_eq!(ranked(start + HOUR * 2), vec!["Pasta", "paris"]);
+        assert_eq!(trie.score_at("paris", start + HOUR * 2), Some(8));
+        // an instant before the epoch counts as the epoch
+        let before = start - Duration::from_secs(1);
+        assert_eq!(trie.score_at("paris", before), trie.score_at("paris", start));
+        assert_eq!(trie.suggestions("pa").len(), 2);
+    }
+
+    #[test]
+    fn decaying_payloads_test() {
+        let input = vec![("paris", 10, 75), ("pasta", 5, 0)];
+        let mut trie = DecayingTrie::with_builder_and_payloads(TrieBuilder::new(), input, HOUR);
+        let start = trie.epoch;
+        let payloads = |trie: &DecayingTrie<u32>, now: Instant| -> Vec<(String, u32, u32)> {
+            trie.suggestions_at("pa", 5, now)
+                .iter()
+                .map(|s| ((*s.word).clone(), s.score(), *s.payload()))
+                .collect()
+        };
+        assert_eq!(
+            payloads(&trie, start + HOUR),
+            vec![("paris".to_string(), 5, 75), ("pasta".to_string(), 3, 0)]
+        );
+        // the payload is kept when the score is updated
+        assert_eq!(trie.hit_at("paris", 1, start + HOUR), 6);
+        assert_eq!(trie.upsert_at("pasta", 8, start + HOUR), Some(3));
+        trie.upsert_with_payload_at("panama", 1, 507, start + HOUR);
+        assert_eq!(
+            payloads(&trie, start + HOUR),
+            vec![
+                ("pasta".to_string(), 8, 0),
+                ("paris".to_string(), 6, 75),
+                ("panama".to_string(), 1, 507)
+            ]
+        );
+    }
+
+    #[test]
+    fn rank_test() {
+        // the scores below 1 at the epoch rank by their values too
+        assert!(rank(1.0) > rank(0.0));
+        assert!(rank(0.0) > rank(-1.0));
+        assert!(rank(-1.0) > rank(-20.0));
+        assert_eq!(rank(f64::NEG_INFINITY), 0);
+    }
+
+    #[test]
+    fn decaying_rebase_test() {
+        // the forward decayed scores after 10 days of half lives of a second are rebased
+        let second = Duration::from_secs(1);
+        let mut trie = DecayingTrie::new(&[("old", 1000)], second);
+        let start = trie.epoch;
+        let later = start + HOUR * 24 * 10;
+        trie.upsert_at("new", 5, later);
+        assert_ne!(trie.epoch, start);
+        assert_eq!(trie.hit_at("newer", 3, later), 3);
+        assert_eq!(trie.hit_at("newer", 3, later + second), 5);
+        let ranked: Vec<(String, u32)> = trie
+            .suggestions_at("", 5, later + second)
+            .iter()
+            .map(|s| ((*s.word).clone(), s.score()))
+            .collect();
+        let expected = [("newer", 5), ("new", 3), ("old", 0)];
+        let expected: Vec<(String, u32)> =
+            expected.iter().map(|(w, s)| (w.to_string(), *s)).collect();
+        assert_eq!(ranked, expected);
+    }
+
+    #[test]
+    fn decaying_trie_vs_naive_test() {
+        let mut rng = Rng(0xdeca_7ed0_2468_ace0);
+        let corpus = multi_script_corpus(&mut rng, 300);
+        let data = as_data(&corpus);
+        let builder = TrieBuilder::new().max_suggestions(3);
+        let trie = DecayingTrie::with_builder(builder, &data, HOUR);
+        let naive = NaiveAutoComplete::new(&data);
+        // nothing is decayed at the epoch
+        assert_same(
+            &prefixes(&data, 2),
+            &[1, 5],
+            |prefix, limit| trie.suggestions_at(prefix, limit, trie.epoch),
+            |prefix, limit| naive.suggestions_with_limit(prefix, limit),
+            "",
+        );
+    }
+}
@@ -0,0 +1,554 @@
+//! The flat module. This module implements a read-only, pointer free layout of a `Trie`.
+//! The layout is queried in place, i.e. a file can be memory mapped (see `FlatTrie::open`) and shared
+//! by several processes through the page cache.
+//!
+//! The layout is (all integers are little endian, offsets are `u64`s from the start of the bytes):
+//! - the header: the magic bytes `b"FTRI"`, the `VERSION` (`u32`), `max_suggestions` (`u32`),
+//!   4 reserved bytes, the offset of the root node and the total length
+//! - the suggestions: the score (`u32`), the length of the word (`u32`) and its utf-8 bytes
+//! - the nodes (children before their parent, i.e. at lower offsets): the offset of the node's
+//!   suggestion (`NONE` if absent), the number of top suggestions (`u32`), the number of edges
+//!   (`u32`), the offsets of the top suggestions (highest first) and the edges sorted by their first
+//!   char. An edge is the first char (`u32`), the length of the part (`u32`), the offset of the part
+//!   and the offset of the child node.
+//!
+//! The tags are not stored, and a trie with selection boosts can't be saved (like a snapshot).
+use crate::internal::{add_bounded, Node};
+use crate::normalize::{Identity, Normalizer};
+use crate::trie::TrieConfig;
+use crate::{AutoCompletor, Suggestion};
+use memmap2::Mmap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryInto;
+use std::fmt::{self, Debug};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"FTRI";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+const NODE_LEN: u64 = 16;
+const EDGE_LEN: u64 = 24;
+/// The offset of a missing suggestion
+const NONE: u64 = u64::MAX;
+
+/// A read-only trie that is queried in place from its (flat) bytes, e.g. a memory mapped file.
+/// The bytes are written with `Trie::save_flat`.
+pub struct FlatTrie<B = Mmap> {
+    bytes: B,
+    root: u64,
+    max_suggestions: usize,
+    normalizer: Arc<dyn Normalizer>,
+}
+
+/// A node read from the flat bytes
+struct FlatNode {
+    offset: u64,
+    suggestion: u64,
+    top_count: u64,
+    edge_count: u64,
+}
+
+/// An edge read from the flat bytes
+struct FlatEdge<'a> {
+    first: u32,
+    part: &'a [u8],
+    child: u64,
+}
+
+impl FlatTrie<Mmap> {
+    /// Memory maps the file written with `Trie::save_flat`.
+    /// The file must not be modified while it is mapped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the mapping is read-only, modifying the file while mapped is documented as unsupported
+        let bytes = unsafe { Mmap::map(&file)? };
+        FlatTrie::new(bytes)
+    }
+}
+
+impl<B: AsRef<[u8]>> FlatTrie<B> {
+    /// Initializes the trie from the bytes written with `Trie::save_flat` (only the header is validated)
+    pub fn new(bytes: B) -> io::Result<Self> {
+        let data = bytes.as_ref();
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err(invalid_data("not a flat trie"));
+        }
+        let version = u32_at(data, 4).unwrap_or_default();
+        if version != VERSION {
+            return Err(invalid_data(&format!("unsupported flat trie version {}", version)));
+        }
+        let max_suggestions = (u32_at(data, 8).unwrap_or_default() as usize).max(1);
+        let root = u64_at(data, 16).unwrap_or(NONE);
+        let len = u64_at(data, 24).unwrap_or_default();
+        if len != data.len() as u64 || root.saturating_add(NODE_LEN) > len {
+            return Err(invalid_data("truncated flat trie"));
+        }
+        Ok(FlatTrie {
+            bytes,
+            root,
+            max_suggestions,
+            normalizer: Arc::new(Identity),
+        })
+    }
+
+    /// Sets the `Normalizer` applied to the prefixes, it must match the one used to build the trie.
+    pub fn with_normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizer = Arc::new(normalizer);
+        self
+    }
+
+    /// Returns the top suggestions for the give `prefix`
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, self.max_suggestions)
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`.
+    /// If `limit` is larger than the cached `max_suggestions`, the subtree of the prefix is walked.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = self.normalizer.normalize(prefix);
+        self.find(&prefix, limit).unwrap_or_default()
+    }
+
+    fn find(&self, prefix: &str, limit: usize) -> Option<Vec<Suggestion>> {
+        let node = self.walk(prefix)?;
+        if limit <= self.max_suggestions {
+            return self.top_suggestions(&node).take(limit).collect();
+        }
+        let mut collected = BTreeSet::new();
+        self.collect_suggestions(node, limit, &mut collected)?;
+        Some(collected.into_iter().rev().collect())
+    }
+
+    /// Returns the node that holds the suggestions for the given `input` (if any)
+    fn walk(&self, mut input: &str) -> Option<FlatNode> {
+        let mut node = self.node(self.root)?;
+        while let Some(ch) = input.chars().next() {
+            let edge = self.edge(&node, ch)?;
+            if input.as_bytes().starts_with(edge.part) {
+                // exhausted all characters in part, we continue walking
+                input = input.get(edge.part.len()..)?;
+                node = self.node(edge.child)?;
+            } else if edge.part.starts_with(input.as_bytes()) {
+                // exhausted all characters in input, we return this node
+                return self.node(edge.child);
+            } else {
+                return None;
+            }
+        }
+        Some(node)
+    }
+
+    /// Collects the top `limit` suggestions of the subtree of `node`.
+    /// The subtree is walked with an explicit stack, so a deep (or corrupted) trie can't overflow
+    /// the call stack, and a node reached twice (only in a corrupted trie) fails the walk.
+    fn collect_suggestions(
+        &self,
+        node: FlatNode,
+        limit: usize,
+        collected: &mut BTreeSet<Suggestion>,
+    ) -> Option<()> {
+        let mut stack = vec![node];
+        let mut visited = HashSet::new();
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node.offset) {
+                return None;
+            }
+            let best = match self.top_suggestions(&node).next() {
+                Some(best) => Some(best?),
+                None => None,
+            };
+            // skip the node if even its best suggestion can not make it into the collected set
+            if collected.len() == limit && best.as_ref() <= collected.iter().next() {
+                continue;
+            }
+            if node.suggestion != NONE {
+                add_bounded(collected, self.suggestion(node.suggestion)?, limit);
+            }
+            for index in (0..node.edge_count).rev() {
+                stack.push(self.node(self.edge_at(&node, index)?.child)?);
+            }
+        }
+        Some(())
+    }
+
+    fn top_suggestions<'a>(
+        &'a self,
+        node: &FlatNode,
+    ) -> impl Iterator<Item = Option<Suggestion>> + 'a {
+        let start = node.offset + NODE_LEN;
+        (0..node.top_count).map(move |index| {
+            let offset = u64_at(self.bytes.as_ref(), start + index * 8)?;
+            self.suggestion(offset)
+        })
+    }
+
+    fn node(&self, offset: u64) -> Option<FlatNode> {
+        let data = self.bytes.as_ref();
+        if offset >= data.len() as u64 {
+            return None;
+        }
+        Some(FlatNode {
+            offset,
+            suggestion: u64_at(data, offset)?,
+            top_count: u32_at(data, offset + 8)? as u64,
+            edge_count: u32_at(data, offset + 12)? as u64,
+        })
+    }
+
+    /// Finds the edge starting with `ch` (the edges are sorted by their first char)
+    fn edge(&self, node: &FlatNode, ch: char) -> Option<FlatEdge<'_>> {
+        let (mut low, mut high) = (0, node.edge_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let edge = self.edge_at(node, middle)?;
+            if edge.first == ch as u32 {
+                return Some(edge);
+            } else if edge.first < ch as u32 {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        None
+    }
+
+    /// Reads the edge at `index` of the `node`. The children are written before their parent, an
+    /// edge to a later node (e.g. to the node itself) is corrupted and would walk in a cycle.
+    fn edge_at(&self, node: &FlatNode, index: u64) -> Option<FlatEdge<'_>> {
+        let data = self.bytes.as_ref();
+        let offset = node.offset + NODE_LEN + node.top_count * 8 + index * EDGE_LEN;
+        let part_len = u32_at(data, offset + 4)? as u64;
+        let part_offset = u64_at(data, offset + 8)?;
+        let child = u64_at(data, offset + 16)?;
+        if child >= node.offset {
+            return None;
+        }
+        Some(FlatEdge {
+            first: u32_at(data, offset)?,
+            part: bytes_at(data, part_offset, part_len)?,
+            child,
+        })
+    }
+
+    fn suggestion(&self, offset: u64) -> Option<Suggestion> {
+        let data = self.bytes.as_ref();
+        if offset >= data.len() as u64 {
+            return None;
+        }
+        let score = u32_at(data, offset)?;
+        let len = u32_at(data, offset + 4)? as u64;
+        let word = std::str::from_utf8(bytes_at(data, offset + 8, len)?).ok()?;
+        Some(Suggestion::new(Arc::new(word.into()), score))
+    }
+}
+
+impl<B> Debug for FlatTrie<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlatTrie")
+            .field("root", &self.root)
+            .field("max_suggestions", &self.max_suggestions)
+            .field("normalizer", &self.normalizer)
+            .finish()
+    }
+}
+
+impl<B: AsRef<[u8]> + Send + Sync> AutoCompletor for FlatTrie<B> {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions(prefix)
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
+}
+
+/// Writes the flat layout of the trie rooted at `root`.
+/// The layout is streamed to the `writer`, it is not buffered: the offsets of the nodes are computed
+/// by a first (dry) run that writes nowhere, only the offsets of the suggestions are kept in memory.
+pub(crate) fn save(root: &Node, config: &TrieConfig, writer: &mut impl Write) -> io::Result<()> {
+    let max_suggestions: u32 = config
+        .max_suggestions
+        .try_into()
+        .map_err(|_| invalid_data("max_suggestions is too large"))?;
+    let mut dry_run = Tracked::new(io::sink());
+    dry_run.write(&[0u8; HEADER_LEN])?;
+    let mut offsets = HashMap::new();
+    save_suggestions(root, &mut dry_run, &mut offsets)?;
+    let root_offset = save_node(root, &offsets, &mut dry_run)?;
+    let len = dry_run.offset;
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..12].copy_from_slice(&max_suggestions.to_le_bytes());
+    header[16..24].copy_from_slice(&root_offset.to_le_bytes());
+    header[24..32].copy_from_slice(&len.to_le_bytes());
+    // the parts of the layout are small, they are batched before they reach the `writer`
+    let mut tracked = Tracked::new(BufWriter::new(writer));
+    tracked.write(&header)?;
+    // the same traversal writes the suggestions at the same offsets
+    save_suggestions(root, &mut tracked, &mut HashMap::new())?;
+    save_node(root, &offsets, &mut tracked)?;
+    tracked.writer.flush()
+}
+
+/// A writer that tracks the offset of the next byte
+struct Tracked<W> {
+    writer: W,
+    offset: u64,
+}
+
+impl<W: Write> Tracked<W> {
+    fn new(writer: W) -> Self {
+        Tracked {
+            writer,
+            offset: 0,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn save_suggestions<'a, W: Write>(
+    node: &'a Node,
+    writer: &mut Tracked<W>,
+    offsets: &mut HashMap<&'a Suggestion, u64>,
+) -> io::Result<()> {
+    if let Some(suggestion) = &node.suggestion {
+        if !offsets.contains_key(suggestion) {
+            offsets.insert(suggestion, writer.offset);
+            writer.write(&suggestion.score().to_le_bytes())?;
+            writer.write(&(suggestion.word.len() as u32).to_le_bytes())?;
+            writer.write(suggestion.word.as_bytes())?;
+        }
+    }
+    for edge in node.edges.values() {
+        save_suggestions(&edge.node, writer, offsets)?;
+    }
+    Ok(())
+}
+
+/// Writes the node (after its children and their parts) and returns its offset
+fn save_node<W: Write>(
+    node: &Node,
+    offsets: &HashMap<&Suggestion, u64>,
+    writer: &mut Tracked<W>,
+) -> io::Result<u64> {
+    let offset = |suggestion: &Suggestion| {
+        offsets
+            .get(suggestion)
+            .copied()
+            .ok_or_else(|| invalid_data("top suggestion is missing from the trie"))
+    };
+    let mut entries = vec![];
+    // the edges are ordered by their first char, as required by the binary search
+    for (ch, edge) in node.edges.iter() {
+        let child = save_node(&edge.node, offsets, writer)?;
+        let part = writer.offset;
+        writer.write(edge.part.as_bytes())?;
+        entries.push((*ch as u32, edge.part.len() as u32, part, child));
+    }
+    let node_offset = writer.offset;
+    let suggestion = match &node.suggestion {
+        Some(suggestion) => offset(suggestion)?,
+        None => NONE,
+    };
+    writer.write(&suggestion.to_le_bytes())?;
+    writer.write(&(node.top_suggestions.len() as u32).to_le_bytes())?;
+    writer.write(&(entries.len() as u32).to_le_bytes())?;
+    for suggestion in node.top_suggestions.iter().rev() {
+        writer.write(&offset(suggestion)?.to_le_bytes())?;
+    }
+    for (first, part_len, part, child) in entries {
+        writer.write(&first.to_le_bytes())?;
+        writer.write(&part_len.to_le_bytes())?;
+        writer.write(&part.to_le_bytes())?;
+        writer.write(&child.to_le_bytes())?;
+    }
+    Ok(node_offset)
+}
+
+fn bytes_at(data: &[u8], offset: u64, len: u64) -> Option<&[u8]> {
+    let start: usize = offset.try_into().ok()?;
+    let end = start.checked_add(len.try_into().ok()?)?;
+    data.get(start..end)
+}
+
+fn u32_at(data: &[u8], offset: u64) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes_at(data, offset, 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], offset: u64) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes_at(data, offset, 8)?.try_into().ok()?))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlatTrie;
+    use crate::normalize::StandardNormalizer;
+    use crate::trie::TrieBuilder;
+    use crate::AutoCompletor;
+    use std::convert::TryInto;
+    use std::io::{self, ErrorKind, Write};
+    use std::{env, fs, process};
+
+    fn data() -> Vec<(&'static str, u32)> {
+        vec![
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 3),
+            ("cameo", 3),
+            ("Café", 3),
+            ("日本", 2),
+            ("日本語", 4),
+            ("ball", 4),
+            ("baller", 5),
+            ("", 1),
+        ]
+    }
+
+    #[test]
+    fn flat_trie_suggestions_test() {
+        let trie = TrieBuilder::new().max_suggestions(3).build(&data());
+        let mut bytes = vec![];
+        trie.save_flat(&mut bytes).unwrap();
+        let flat = FlatTrie::new(bytes).unwrap();
+        let prefixes = ["", "c", "ca", "car", "carpe", "carpeo", "b", "baller", "日", "日本語", "x"];
+        for prefix in prefixes.iter() {
+            assert_eq!(trie.suggestions(prefix), flat.suggestions(prefix), "\nPrefix: {}", prefix);
+            for limit in 0..14 {
+                assert_eq!(
+                    trie.suggestions_with_limit(prefix, limit),
+                    flat.suggestions_with_limit(prefix, limit),
+                    "\nPrefix: {}, limit: {}",
+                    prefix,
+                    limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_trie_mmap_test() {
+        let normalizer = StandardNormalizer::new().lowercase();
+        let trie = TrieBuilder::new().normalizer(normalizer).build(&data());
+        let path = env::temp_dir().join(format!("flat_trie_mmap_test_{}.bin", process::id()));
+        let mut file = fs::File::create(&path).unwrap();
+        trie.save_flat(&mut file).unwrap();
+        drop(file);
+        let flat: Box<dyn AutoCompletor> =
+            Box::new(FlatTrie::open(&path).unwrap().with_normalizer(normalizer));
+        assert_eq!(flat.suggestions("CA"), trie.suggestions("CA"));
+        assert_eq!(flat.suggestions("caf").len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flat_trie_invalid_test() {
+        let mut bytes = vec![];
+        TrieBuilder::new().build(&data()).save_flat(&mut bytes).unwrap();
+        let error = FlatTrie::new(&b"NOPE"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = FlatTrie::new(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        // corrupted nodes never panic, they just have no suggestions
+        let mut corrupted = bytes.clone();
+        let len = corrupted.len();
+        corrupted[len - 24..].iter_mut().for_each(|b| *b = 0xff);
+        let flat = FlatTrie::new(&corrupted[..]).unwrap();
+        flat.suggestions("c");
+        flat.suggestions_with_limit("", 20);
+    }
+
+    #[test]
+    fn flat_trie_selections_test() {
+        let mut trie = TrieBuilder::new().build(&data());
+        trie.record_selection("ca", "cain");
+        let mut bytes = vec![];
+        let error = trie.save_flat(&mut bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(bytes.is_empty());
+        trie.clear_selections();
+        trie.save_flat(&mut bytes).unwrap();
+        let flat = FlatTrie::new(bytes).unwrap();
+        assert_eq!(flat.suggestions("ca"), trie.suggestions("ca"));
+    }
+
+    /// Fails the writes after `capacity` bytes
+    struct Limited {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Write for Limited {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            if self.written.len() + bytes.len() > self.capacity {
+                return Err(io::Error::new(ErrorKind::WriteZero, "full"));
+            }
+            self.written.extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flat_trie_streamed_test() {
+        let trie = TrieBuilder::new().build(&data());
+        let mut bytes = vec![];
+        trie.save_flat(&mut bytes).unwrap();
+        let mut writer = Limited {
+            written: vec![],
+            capacity: bytes.len(),
+        };
+        trie.save_flat(&mut writer).unwrap();
+        assert_eq!(writer.written, bytes);
+        let mut writer = Limited {
+            written: vec![],
+            capacity: bytes.len() - 1,
+        };
+        assert_eq!(trie.save_flat(&mut writer).unwrap_err().kind(), ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn flat_trie_cycle_test() {
+        let mut bytes = vec![];
+        TrieBuilder::new().build(&data()).save_flat(&mut bytes).unwrap();
+        let u64_at = |bytes: &[u8], offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+        };
+        let root = u64_at(&bytes, 16);
+        let top_count = u32::from_le_bytes(bytes[root + 8..root + 12].try_into().unwrap());
+        let edges = root + 16 + top_count as usize * 8;
+        let child = |edge: usize| edges + edge * 24 + 16;
+        // every edge of the root points at the root itself
+        let mut cyclic = bytes.clone();
+        for edge in 0..4 {
+            cyclic[child(edge)..child(edge) + 8].copy_from_slice(&(root as u64).to_le_bytes());
+        }
+        let flat = FlatTrie::new(&cyclic[..]).unwrap();
+        assert_eq!(flat.suggestions("c"), vec![]);
+        assert_eq!(flat.suggestions_with_limit("", 20), vec![]);
+        // two edges share a child, the walk would visit it twice
+        let mut shared = bytes.clone();
+        let first = u64_at(&bytes, child(0));
+        shared[child(1)..child(1) + 8].copy_from_slice(&(first as u64).to_le_bytes());
+        let flat = FlatTrie::new(&shared[..]).unwrap();
+        assert_eq!(flat.suggestions_with_limit("", 20), vec![]);
+        assert_eq!(flat.suggestions("ca").len(), 5);
+    }
+}
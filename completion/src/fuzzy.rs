@@ -0,0 +1,141 @@
+//! The fuzzy module.
+//! This module contains the edit distance (Levenshtein) helpers used for typo tolerant completion.
+
+use crate::Suggestion;
+
+/// A suggestion that matched the prefix with (at most) `distance` edits.
+/// The distance is the smallest edit distance between the prefix and any prefix of the suggestion.
+#[derive(Debug)]
+pub struct FuzzySuggestion<P = ()> {
+    pub suggestion: Suggestion<P>,
+    pub distance: usize,
+}
+
+impl<P> Clone for FuzzySuggestion<P> {
+    fn clone(&self) -> Self {
+        FuzzySuggestion::new(self.suggestion.clone(), self.distance)
+    }
+}
+
+impl<P> PartialEq for FuzzySuggestion<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.suggestion == other.suggestion && self.distance == other.distance
+    }
+}
+
+impl<P> Eq for FuzzySuggestion<P> {}
+
+impl<P> FuzzySuggestion<P> {
+    pub(crate) fn new(suggestion: Suggestion<P>, distance: usize) -> Self {
+        FuzzySuggestion {
+            suggestion,
+            distance,
+        }
+    }
+}
+
+/// The first row of the Levenshtein matrix, i.e. the distances of every prefix of the `query`
+/// to the empty string.
+pub(crate) fn first_row(query: &[char]) -> Vec<usize> {
+    (0..=query.len()).collect()
+}
+
+/// Computes the next row of the Levenshtein matrix when the compared word is extended by `ch`.
+/// `row[i]` is the edit distance between `query[..i]` and the word so far.
+pub(crate) fn next_row(row: &[usize], query: &[char], ch: char) -> Vec<usize> {
+    let mut next = Vec::with_capacity(row.len());
+    next.push(row[0] + 1);
+    for i in 1..row.len() {
+        let substitution = row[i - 1] + if query[i - 1] == ch { 0 } else { 1 };
+        let insertion = next[i - 1] + 1;
+        let deletion = row[i] + 1;
+        next.push(substitution.min(insertion).min(deletion));
+    }
+    next
+}
+
+/// Returns the smallest edit distance between the `query` and any prefix of the `word`.
+pub(crate) fn prefix_distance(query: &[char], word: &str) -> usize {
+    let mut row = first_row(query);
+    let mut best = row[query.len()];
+    for ch in word.chars() {
+        row = next_row(&row, query, ch);
+        best = best.min(row[query.len()]);
+    }
+    best
+}
+
+/// Orders the matches by distance (closest first) and then by suggestion (highest first),
+/// keeping the top `limit` of them.
+pub(crate) fn rank<P>(
+    mut matches: Vec<FuzzySuggestion<P>>,
+    limit: usize,
+) -> Vec<FuzzySuggestion<P>> {
+    matches.sort_by(|a, b| {
+        a.distance
+            .cmp(&b.distance)
+            .then_with(|| b.suggestion.cmp(&a.suggestion))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prefix_distance;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::assert_same;
+    use crate::trie::Trie;
+
+    #[test]
+    fn prefix_distance_test() {
+        let distance = |query: &str, word: &str| {
+            prefix_distance(&query.chars().collect::<Vec<char>>(), word)
+        };
+        assert_eq!(distance("car", "carpenter"), 0);
+        assert_eq!(distance("carpnt", "carpenter"), 1);
+        assert_eq!(distance("cra", "carpenter"), 1);
+        assert_eq!(distance("xyz", "carpenter"), 3);
+        assert_eq!(distance("xarpet", "carpet"), 1);
+        assert_eq!(distance("carpets", "carpet"), 1);
+        assert_eq!(distance("", "carpet"), 0);
+        assert_eq!(distance("ab", ""), 2);
+    }
+
+    #[test]
+    fn trie_vs_naive_fuzzy_test() {
+        let data = [
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 2),
+            ("aba", 3),
+            ("acas", 4),
+            ("ballcdcder", 5),
+            ("caa", 5),
+            ("cascasin", 3),
+            ("cacs", 3),
+            ("bascascll", 4),
+            ("basller", 5),
+            ("cdacs", 3),
+            ("dascascll", 4),
+            ("dasller", 5),
+            ("eeacs", 3),
+            ("escascll", 4),
+            ("eesller", 5),
+        ];
+        let prefixes = [
+            "", "c", "ca", "carpnt", "crapet", "cocon", "basler", "dsa", "eacs", "xyz", "ballcdcderr",
+        ];
+        let trie = Trie::new(&data);
+        let naive = NaiveAutoComplete::new(&data);
+        assert_same(
+            &prefixes,
+            &[0, 1, 2, 3],
+            |prefix, max_edits| trie.fuzzy_suggestions(prefix, max_edits),
+            |prefix, max_edits| naive.fuzzy_suggestions(prefix, max_edits),
+            "",
+        );
+    }
+}
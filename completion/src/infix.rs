@@ -0,0 +1,292 @@
+//! The infix module.
+//! An `InfixTrie` matches the prefix anywhere in a word (e.g. "phone" finds "iphone case"),
+//! not only at its start. Every word is indexed at several offsets (see `InfixMode`) in a `Trie`, keyed by
+//! the rest of the word from that offset. All the hits of a word rank the same, so the cached top
+//! suggestions of the trie hold every word once.
+
+use crate::trie::{Trie, TrieBuilder};
+use crate::{AutoCompletor, Suggestion};
+use std::sync::Arc;
+
+/// Separates the indexed rest of a word from the whole word in the keys of the index,
+/// so the words with the same rest (e.g. "iphone" and "phone") have their own keys.
+pub(crate) const SEPARATOR: char = '\0';
+
+/// The offsets of a word at which a prefix can match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfixMode {
+    /// The start of every token, e.g. "case" in "iphone case".
+    /// A token starts with an alphanumeric character after a non alphanumeric one.
+    WordStarts,
+    /// Every character, e.g. "phone" in "iphone case".
+    /// The index grows with the square of the length of the words.
+    Suffixes,
+}
+
+impl InfixMode {
+    /// Returns the offsets (in chars) of the (normalized) `key` at which a prefix can match
+    pub(crate) fn offsets(self, key: &[char]) -> Vec<usize> {
+        (0..key.len().max(1))
+            .filter(|&i| match self {
+                InfixMode::WordStarts => {
+                    i == 0 || (!key[i - 1].is_alphanumeric() && key[i].is_alphanumeric())
+                }
+                InfixMode::Suffixes => true,
+            })
+            .collect()
+    }
+
+    /// Returns the first offset of the `key` at which the `prefix` matches (if any)
+    pub(crate) fn find(self, key: &[char], prefix: &[char]) -> Option<usize> {
+        self.offsets(key)
+            .into_iter()
+            .find(|&i| key[i.min(key.len())..].starts_with(prefix))
+    }
+}
+
+/// A suggestion that matched the prefix at `offset`, the number of chars before the match
+/// in the normalized word (which is the word itself without a normalizer).
+/// The offset is the first one at which the prefix matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfixSuggestion {
+    pub suggestion: Suggestion,
+    pub offset: usize,
+}
+
+/// Completes the prefixes that occur anywhere in the words, see `InfixMode`
+#[derive(Debug)]
+pub struct InfixTrie {
+    index: Trie,
+    mode: InfixMode,
+}
+
+impl InfixTrie {
+    /// Initializes the infix trie from a given list of suggestions and scores (as a tuple)
+    pub fn new(input: &[(&str, u32)], mode: InfixMode) -> Self {
+        InfixTrie::with_builder(TrieBuilder::new(), input, mode)
+    }
+
+    /// Initializes the infix trie with the configuration and the normalizer of the `builder`,
+    /// e.g. `InfixTrie::with_builder(TrieBuilder::new().max_suggestions(8), &input, mode)`
+    pub fn with_builder(builder: TrieBuilder, input: &[(&str, u32)], mode: InfixMode) -> Self {
+        let mut infix = InfixTrie {
+            index: builder.build_empty(),
+            mode,
+        };
+        input.iter().for_each(|(word, score)| {
+            infix.upsert(word, *score);
+        });
+        infix
+    }
+
+    pub fn mode(&self) -> InfixMode {
+        self.mode
+    }
+
+    /// Returns the keys of the `word` in the index, the first one is the whole word
+    fn keys(&self, word: &str) -> Vec<Vec<char>> {
+        let key = self.index.key(word);
+        self.mode
+            .offsets(&key)
+            .into_iter()
+            .map(|i| {
+                let mut indexed = key[i.min(key.len())..].to_vec();
+                indexed.push(SEPARATOR);
+                indexed.extend_from_slice(&key);
+                indexed
+            })
+            .collect()
+    }
+
+    /// Inserts the given `word` with the `score`, or replaces the score if the word is present.
+    /// Returns the previous suggestion (if any).
+    pub fn upsert(&mut self, word: &str, score: u32) -> Option<Suggestion> {
+        let suggestion = Suggestion::new(Arc::new(word.to_string()), score);
+        let mut previous = None;
+        for key in self.keys(word) {
+            let replaced = self.index.upsert_at(&key, suggestion.clone());
+            previous = previous.or(replaced);
+        }
+        previous
+    }
+
+    /// Removes the given `word` and returns its suggestion (if it was present).
+    pub fn remove(&mut self, word: &str) -> Option<Suggestion> {
+        let mut removed = None;
+        for key in self.keys(word) {
+            let suggestion = self.index.remove_key(&key);
+            removed = removed.or(suggestion);
+        }
+        removed
+    }
+
+    pub fn suggestions(&self, prefix: &str) -> Vec<InfixSuggestion> {
+        self.suggestions_with_limit(prefix, self.index.config().max_suggestions)
+    }
+
+    /// Returns the top `limit` words in which the given `prefix` occurs (at an offset of the mode),
+    /// each word once with the first offset at which the prefix matches.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<InfixSuggestion> {
+        let prefix = self.index.key(prefix);
+        self.index
+            .top_suggestions(&prefix, limit)
+            .into_iter()
+            .map(|suggestion| {
+                let key = self.index.key(&suggestion.word);
+                let offset = self.mode.find(&key, &prefix).unwrap_or(0);
+                InfixSuggestion { suggestion, offset }
+            })
+            .collect()
+    }
+}
+
+impl AutoCompletor for InfixTrie {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, self.index.config().max_suggestions)
+            .into_iter()
+            .map(|infix| infix.suggestion)
+            .collect()
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+            .into_iter()
+            .map(|infix| infix.suggestion)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InfixMode, InfixTrie};
+    use crate::normalize::StandardNormalizer;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::TrieBuilder;
+    use std::collections::HashSet;
+
+    fn matches(infix: &InfixTrie, prefix: &str) -> Vec<(String, usize)> {
+        infix
+            .suggestions(prefix)
+            .iter()
+            .map(|s| ((*s.suggestion.word).clone(), s.offset))
+            .collect()
+    }
+
+    #[test]
+    fn offsets_test() {
+        let key: Vec<char> = "iphone case-xl".chars().collect();
+        assert_eq!(InfixMode::WordStarts.offsets(&key), vec![0, 7, 12]);
+        assert_eq!(InfixMode::Suffixes.offsets(&key).len(), 14);
+        assert_eq!(InfixMode::WordStarts.offsets(&[]), vec![0]);
+        let prefix: Vec<char> = "ca".chars().collect();
+        assert_eq!(InfixMode::WordStarts.find(&key, &prefix), Some(7));
+        assert_eq!(InfixMode::WordStarts.find(&key, &[]), Some(0));
+        assert_eq!(InfixMode::WordStarts.find(&key, &['h']), None);
+        assert_eq!(InfixMode::Suffixes.find(&key, &['h']), Some(2));
+    }
+
+    #[test]
+    fn infix_suggestions_test() {
+        let input = [
+            ("iphone case", 5),
+            ("phone", 3),
+            ("headphones", 4),
+            ("banana", 2),
+            ("case", 1),
+        ];
+        let mut infix = InfixTrie::new(&input, InfixMode::Suffixes);
+        assert_eq!(
+            matches(&infix, "phone"),
+            vec![
+                ("iphone case".to_string(), 1),
+                ("headphones".to_string(), 4),
+                ("phone".to_string(), 0),
+            ]
+        );
+        // "banana" matches "an" twice, but is suggested once (at the first match)
+        assert_eq!(matches(&infix, "an"), vec![("banana".to_string(), 1)]);
+        let case = vec![("iphone case".to_string(), 7), ("case".to_string(), 0)];
+        assert_eq!(matches(&infix, "case"), case);
+        assert_eq!(matches(&infix, "x"), vec![]);
+        assert_eq!(infix.suggestions("").len(), 5);
+        assert_eq!(infix.upsert("phone", 6).map(|s| s.score()), Some(3));
+        assert_eq!(matches(&infix, "phone")[0], ("phone".to_string(), 0));
+        assert_eq!(infix.remove("iphone case").map(|s| s.score()), Some(5));
+        assert_eq!(infix.remove("iphone case"), None);
+        assert_eq!(matches(&infix, "case"), vec![("case".to_string(), 0)]);
+        assert_eq!(infix.suggestions_with_limit("e", 2).len(), 2);
+
+        let words = InfixTrie::new(&input, InfixMode::WordStarts);
+        assert_eq!(matches(&words, "phone"), vec![("phone".to_string(), 0)]);
+        assert_eq!(matches(&words, "ca"), case);
+    }
+
+    #[test]
+    fn infix_normalizer_test() {
+        let builder = TrieBuilder::new().normalizer(StandardNormalizer::new().lowercase());
+        let input = [("New York", 2), ("York", 1)];
+        let infix = InfixTrie::with_builder(builder, &input, InfixMode::WordStarts);
+        assert_eq!(
+            matches(&infix, "YO"),
+            vec![("New York".to_string(), 4), ("York".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn trie_vs_naive_infix_test() {
+        let mut rng = Rng(0x1f1c_0ff5_e7a5_9999);
+        // a few "tokens" per word, so the word starts are not only at the beginning
+        let mut corpus: Vec<(String, u32)> = multi_script_corpus(&mut rng, 200)
+            .into_iter()
+            .map(|(word, score)| {
+                let cut = word.char_indices().map(|(i, _)| i).nth(rng.next(4));
+                match cut {
+                    Some(cut) if cut > 0 => (format!("{} {}", &word[..cut], &word[cut..]), score),
+                    _ => (word, score),
+                }
+            })
+            .collect();
+        // unique words, so the updates below are easy to follow in the reference
+        let mut seen = HashSet::new();
+        corpus.retain(|(word, _)| seen.insert(word.clone()));
+        let mut data = as_data(&corpus);
+        // the substrings of the words up to 2 chars are the prefixes of their suffixes
+        let suffixes: Vec<(&str, u32)> = data
+            .iter()
+            .flat_map(|(word, _)| word.char_indices().map(move |(i, _)| (&word[i..], 0)))
+            .collect();
+        let prefixes = prefixes(&suffixes, 2);
+        for mode in [InfixMode::WordStarts, InfixMode::Suffixes].iter() {
+            let builder = TrieBuilder::new().max_suggestions(3);
+            let mut infix = InfixTrie::with_builder(builder, &data, *mode);
+            let mut naive = NaiveAutoComplete::new(&data);
+            let assert_all = |infix: &InfixTrie, naive: &NaiveAutoComplete| {
+                assert_same(
+                    &prefixes,
+                    &[1, 5],
+                    |prefix, limit| infix.suggestions_with_limit(prefix, limit),
+                    |prefix, limit| naive.infix_suggestions(prefix, limit, *mode),
+                    &format!("mode: {:?}", mode),
+                );
+            };
+            assert_all(&infix, &naive);
+            // update and remove some words
+            let (updated, removed) = data.split_at(data.len() / 2);
+            for (word, score) in updated.iter().step_by(3) {
+                infix.upsert(word, score + 7);
+            }
+            for (word, _) in removed.iter() {
+                infix.remove(word);
+            }
+            let remaining: Vec<(&str, u32)> = updated
+                .iter()
+                .enumerate()
+                .map(|(i, (w, s))| (*w, if i % 3 == 0 { s + 7 } else { *s }))
+                .collect();
+            naive = NaiveAutoComplete::new(&remaining);
+            assert_all(&infix, &naive);
+            data.reverse();
+        }
+    }
+}
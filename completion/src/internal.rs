@@ -1,8 +1,10 @@
 //! The internal module. This module defines the internal data structures used in the trie
+use crate::page::Cursor;
+use crate::tag::{Tag, TagFilter};
 use crate::Suggestion;
-use std::{collections::BTreeSet, option::Option, cmp::Ordering, rc::Rc};
+use std::{collections::{BTreeSet, BinaryHeap}, option::Option, cmp::Ordering, sync::Arc};
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fmt::{Debug, Error},
     result::Result,
 };
@@ -10,101 +12,272 @@ use std::{
 /// A node contains a list of edges (represented as a character to edge map).
 /// Each edge is associated with the part of the string.
 /// A node contains a map of edges (to nodes) and a flag to indicate a terminal node.
-pub(crate) struct Node {
-    pub(crate) edges: HashMap<char, Edge>,
-    pub(crate) suggestion: Option<Rc<Suggestion>>,
-    pub(crate) top_suggestions: BTreeSet<Rc<Suggestion>>,
+/// The edges are ordered by their first character, so a walk visits the words in lexicographic order.
+pub(crate) struct Node<P = ()> {
+    pub(crate) edges: BTreeMap<char, Edge<P>>,
+    pub(crate) suggestion: Option<Suggestion<P>>,
+    pub(crate) top_suggestions: BTreeSet<Suggestion<P>>,
+    /// The tags of the suggestion of this node
+    pub(crate) tags: BTreeSet<Tag>,
+    /// The tags of all the suggestions in the subtree (rooted at this node)
+    pub(crate) subtree_tags: BTreeSet<Tag>,
 }
 
-impl Debug for Node {
+impl<P: Debug> Debug for Node<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), Error> {
         f.debug_struct("Node")
             .field("edges", &self.edges)
             .field("suggestion", &self.suggestion)
             .field("top_suggestions", &self.top_suggestions)
+            .field("tags", &self.tags)
             .finish()
     }
 }
 
-impl PartialEq for Node {
+impl<P> PartialEq for Node<P> {
     fn eq(&self, other: &Self) -> bool {
-        let mut mine: Vec<&Rc<Suggestion>> = self.top_suggestions.iter().collect();
+        let mut mine: Vec<&Suggestion<P>> = self.top_suggestions.iter().collect();
         mine.sort();
-        let mut theirs: Vec<&Rc<Suggestion>> = other.top_suggestions.iter().collect();
+        let mut theirs: Vec<&Suggestion<P>> = other.top_suggestions.iter().collect();
         theirs.sort();
-        self.edges == other.edges && self.suggestion == other.suggestion && mine == theirs
+        self.edges == other.edges
+            && self.suggestion == other.suggestion
+            && mine == theirs
+            && self.tags == other.tags
     }
 }
 
-impl Node {
-    fn new(suggestion: Rc<Suggestion>) -> Self {
+impl<P> Node<P> {
+    fn new(suggestion: Suggestion<P>, max_suggestions: usize) -> Self {
         let mut node = Node::empty();
-        node.add_suggestion(suggestion.clone());
+        node.add_suggestion(suggestion.clone(), max_suggestions);
         node.suggestion = Some(suggestion);
         node
     }
 
     pub(crate) fn empty() -> Self {
         Node {
-            edges: HashMap::new(),
+            edges: BTreeMap::new(),
             suggestion: None,
             top_suggestions: BTreeSet::new(),
+            tags: BTreeSet::new(),
+            subtree_tags: BTreeSet::new(),
         }
     }
 
-    pub(crate) fn with_top_suggestions<'a>(
-        &'a mut self,
-        mut suggestions: Vec<(&str, u32)>,
-    ) -> &'a mut Self {
+    pub(crate) fn add_suggestion(&mut self, suggestion: Suggestion<P>, max_suggestions: usize) {
+        add_bounded(&mut self.top_suggestions, suggestion, max_suggestions);
+    }
+
+    /// Recomputes the top suggestions of this node from its own suggestion and
+    /// the top suggestions of its child nodes.
+    /// The children are expected to be up to date.
+    pub(crate) fn refresh_top_suggestions(&mut self, max_suggestions: usize) {
         self.top_suggestions.clear();
-        suggestions.drain(0..).for_each(|(w, s)| {
-            self.add_suggestion(Rc::new(Suggestion::new(Rc::new(w.into()), s)));
-        });
-        self
+        if let Some(suggestion) = self.suggestion.clone() {
+            self.add_suggestion(suggestion, max_suggestions);
+        }
+        let children: Vec<Suggestion<P>> = self
+            .edges
+            .values()
+            .flat_map(|edge| edge.node.top_suggestions.iter().cloned())
+            .collect();
+        children
+            .into_iter()
+            .for_each(|suggestion| self.add_suggestion(suggestion, max_suggestions));
+    }
+
+    /// Recomputes the tags of the subtree from the tags of this node and of its child nodes.
+    /// The children are expected to be up to date.
+    pub(crate) fn refresh_tags(&mut self) {
+        let mut subtree_tags = self.tags.clone();
+        for edge in self.edges.values() {
+            subtree_tags.extend(edge.node.subtree_tags.iter().cloned());
+        }
+        self.subtree_tags = subtree_tags;
     }
 
-    pub(crate) fn add_suggestion(&mut self, suggestion: Rc<Suggestion>) {
-        if self.top_suggestions.len() < 5 {
-            // keep the size of the suggestions to 10
-            self.top_suggestions.insert(suggestion);
-        } else {
-            let min_score_suggestion = self.first_suggestion().unwrap().clone();
-            // if current min < suggestion score, pop the min and add the sugestion
-            // we always want to keep a min heap of top scores
-            if suggestion.partial_cmp(&min_score_suggestion) == Some(Ordering::Greater){
-                self.top_suggestions.remove(&min_score_suggestion);
-                self.top_suggestions.insert(suggestion);
+    /// Collects the top `limit` suggestions of the whole subtree (rooted at this node) into `collected`.
+    /// A child is skipped when even its best suggestion cannot make it into a full `collected` set.
+    pub(crate) fn collect_suggestions(&self, limit: usize, collected: &mut BTreeSet<Suggestion<P>>) {
+        if let Some(suggestion) = &self.suggestion {
+            add_bounded(collected, suggestion.clone(), limit);
+        }
+        for edge in self.edges.values() {
+            let best = edge.node.top_suggestions.iter().next_back();
+            let worst_collected = collected.iter().next();
+            if collected.len() == limit && best <= worst_collected {
+                continue;
             }
+            edge.node.collect_suggestions(limit, collected);
         }
     }
 
-    fn first_suggestion(&self) -> Option<&Rc<Suggestion>> {
-        self.top_suggestions.iter().nth(0)
+    /// Returns the (at most) `size` best suggestions of the whole subtree that rank below the
+    /// `cursor` (if any) and pass the `filter` (if any), and whether there are more of them.
+    /// This is a best-first search: a child is only visited when its best suggestion (below the cursor)
+    /// could be next, so a page costs about `size` visits no matter how deep it is.
+    /// A child without any tag of the filter is skipped, the others are searched below their
+    /// top suggestions when the filter removes those.
+    pub(crate) fn page(
+        &self,
+        cursor: Option<&Cursor>,
+        size: usize,
+        max_suggestions: usize,
+        filter: Option<&TagFilter>,
+    ) -> (Vec<Suggestion<P>>, bool) {
+        let below =
+            |suggestion: &Suggestion<P>| cursor.is_none_or(|cursor| cursor.is_above(suggestion));
+        let passes = |tags: &BTreeSet<Tag>| filter.is_none_or(|filter| filter.matches(tags));
+        let mut heap = BinaryHeap::new();
+        if passes(&self.subtree_tags) {
+            if let Some(bound) = self.bound(cursor, max_suggestions) {
+                heap.push(Candidate::Node(bound, self));
+            }
+        }
+        let mut suggestions = vec![];
+        // one more, to know if there is a next page
+        while suggestions.len() <= size {
+            match heap.pop() {
+                Some(Candidate::Suggestion(suggestion)) => suggestions.push(suggestion.clone()),
+                Some(Candidate::Node(_, node)) => {
+                    let suggestion = node.suggestion.as_ref().filter(|s| below(s));
+                    if let Some(suggestion) = suggestion.filter(|_| passes(&node.tags)) {
+                        heap.push(Candidate::Suggestion(suggestion));
+                    }
+                    for edge in node.edges.values().filter(|e| passes(&e.node.subtree_tags)) {
+                        if let Some(bound) = edge.node.bound(cursor, max_suggestions) {
+                            heap.push(Candidate::Node(bound, &edge.node));
+                        }
+                    }
+                }
+                None => break,
+            }
+        }
+        let more = suggestions.len() > size;
+        suggestions.truncate(size);
+        (suggestions, more)
+    }
+
+    /// Returns an upper bound of the suggestions in this subtree that rank below the `cursor`,
+    /// or `None` if there are none.
+    fn bound(&self, cursor: Option<&Cursor>, max_suggestions: usize) -> Option<&Suggestion<P>> {
+        let best_below = self
+            .top_suggestions
+            .iter()
+            .rev()
+            .find(|suggestion| cursor.is_none_or(|cursor| cursor.is_above(suggestion)));
+        match best_below {
+            // the top suggestions are the best of the subtree, i.e. this is the best below the cursor
+            Some(best) => Some(best),
+            // the top suggestions are the whole subtree
+            None if self.top_suggestions.len() < max_suggestions => None,
+            // the rest of the subtree ranks below the worst top suggestion
+            None => self.top_suggestions.iter().next(),
+        }
     }
 
-    pub(crate) fn sorted_suggestions(&self) -> Vec<Rc<Suggestion>> {
+    pub(crate) fn sorted_suggestions(&self) -> Vec<Suggestion<P>> {
         self.top_suggestions
             .iter()
             .rev()
-            .map(|s| s.clone())
-            .collect::<Vec<Rc<Suggestion>>>()
+            .cloned()
+            .collect::<Vec<Suggestion<P>>>()
+    }
+}
+
+/// A suggestion or a node (with the upper bound of its suggestions) waiting to be visited
+/// in a best-first search. The candidates are ordered by their (upper bound of the) suggestion.
+enum Candidate<'a, P> {
+    Suggestion(&'a Suggestion<P>),
+    Node(&'a Suggestion<P>, &'a Node<P>),
+}
+
+impl<'a, P> Candidate<'a, P> {
+    fn key(&self) -> &'a Suggestion<P> {
+        match self {
+            Candidate::Suggestion(suggestion) => suggestion,
+            Candidate::Node(bound, _) => bound,
+        }
+    }
+}
+
+impl<'a, P> PartialEq for Candidate<'a, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, P> Eq for Candidate<'a, P> {}
+
+impl<'a, P> Ord for Candidate<'a, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(other.key())
+    }
+}
+
+impl<'a, P> PartialOrd for Candidate<'a, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Node {
+    pub(crate) fn with_top_suggestions<'a>(
+        &'a mut self,
+        mut suggestions: Vec<(&str, u32)>,
+    ) -> &'a mut Self {
+        self.top_suggestions.clear();
+        suggestions.drain(0..).for_each(|(w, s)| {
+            self.top_suggestions
+                .insert(Suggestion::new(Arc::new(w.into()), s));
+        });
+        self
+    }
+}
+
+/// Adds the `suggestion` to the `suggestions` set, keeping only the top `max_suggestions` of them
+pub(crate) fn add_bounded<P>(
+    suggestions: &mut BTreeSet<Suggestion<P>>,
+    suggestion: Suggestion<P>,
+    max_suggestions: usize,
+) {
+    // e.g. a word indexed under several keys (see `InfixTrie`) is added once
+    if suggestions.contains(&suggestion) {
+        return;
+    }
+    if suggestions.len() < max_suggestions {
+        suggestions.insert(suggestion);
+    } else if let Some(min_score_suggestion) = suggestions.iter().next().cloned() {
+        // if current min < suggestion score, pop the min and add the sugestion
+        // we always want to keep a min heap of top scores
+        if suggestion.cmp(&min_score_suggestion) == Ordering::Greater {
+            suggestions.remove(&min_score_suggestion);
+            suggestions.insert(suggestion);
+        }
     }
 }
 
 /// Represents an Edge in the trie
 /// Each edge is associated with the part of the string and another node
-#[derive(Debug, PartialEq)]
-pub(crate) struct Edge {
+#[derive(Debug)]
+pub(crate) struct Edge<P = ()> {
     pub(crate) part: String,
-    pub(crate) node: Node,
+    pub(crate) node: Node<P>,
+}
+
+impl<P> PartialEq for Edge<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.part == other.part && self.node == other.node
+    }
 }
 
-impl Edge {
-    pub(crate) fn new_node(part: String, node: Node) -> Self {
+impl<P> Edge<P> {
+    pub(crate) fn new_node(part: String, node: Node<P>) -> Self {
         Edge { part, node }
     }
-    pub(crate) fn new(part: String, suggestion: Rc<Suggestion>) -> Self {
-        Edge::new_node(part, Node::new(suggestion))
+    pub(crate) fn new(part: String, suggestion: Suggestion<P>, max_suggestions: usize) -> Self {
+        Edge::new_node(part, Node::new(suggestion, max_suggestions))
     }
 
     pub(crate) fn empty(part: String) -> Self {
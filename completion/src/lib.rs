@@ -3,58 +3,128 @@
 #![allow(dead_code)]
 
 use core::cmp::Ordering;
-use std::rc::Rc;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
+pub mod decay;
+pub mod flat;
+pub mod fuzzy;
+pub mod infix;
 mod internal;
+pub mod loader;
 pub mod naive;
+pub mod namespace;
+pub mod ngram;
+pub mod normalize;
+pub mod page;
+pub mod personal;
+pub mod phrase;
+pub mod rank;
+mod selection;
+mod snapshot;
+pub mod tag;
+#[cfg(test)]
+mod test_support;
 pub mod trie;
 
+/// The number of suggestions returned for a prefix, unless configured otherwise
+pub const DEFAULT_MAX_SUGGESTIONS: usize = 5;
+
 /// Represents a suggestion, i.e. a full word/sentence with an associated score.
 /// The score is used to rank the suggestions (higher score = higher suggestion)
-#[derive(Debug, Eq, Hash)]
-pub struct Suggestion {
-    pub word: Rc<String>,
+/// A suggestion can carry a payload (e.g. an id or a url), it is allocated once per word
+/// and shared (not copied) by the top suggestions of every prefix of the word.
+/// The payload is not compared, two suggestions are equal if their word and score are equal.
+#[derive(Debug)]
+pub struct Suggestion<P = ()> {
+    pub word: Arc<String>,
     score: u32,
+    payload: Arc<P>,
 }
 
-pub trait AutoCompletor {
-    fn suggestions(&self, prefix: &str) -> Vec<Rc<Suggestion>>;
+/// An auto completor returns the suggestions for a given prefix.
+/// Implementations are `Send + Sync`, i.e. a built completor can be shared across threads (e.g. in an `Arc`)
+/// and queried concurrently without locking.
+pub trait AutoCompletor: Send + Sync {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion>;
+
+    /// Returns (at most) the top `limit` suggestions for the given `prefix`
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion>;
 }
 
 impl Suggestion {
-    pub(crate) fn new(word: Rc<String>, score: u32) -> Self {
-        Suggestion { word, score }
+    pub(crate) fn new(word: Arc<String>, score: u32) -> Self {
+        Suggestion::with_payload(word, score, Arc::new(()))
     }
 }
 
-impl Ord for Suggestion {
-    fn cmp(&self, other: &Self) -> Ordering {
+impl<P> Suggestion<P> {
+    pub(crate) fn with_payload(word: Arc<String>, score: u32, payload: Arc<P>) -> Self {
+        Suggestion {
+            word,
+            score,
+            payload,
+        }
+    }
+
+    /// The score of the suggestion (higher score = higher suggestion)
+    pub fn score(&self) -> u32 {
         self.score
-            .cmp(&other.score)
-            .then_with(|| self.word.cmp(&other.word))
+    }
+
+    /// The payload of the suggestion
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+
+    /// The same suggestion with another score
+    pub(crate) fn with_score(&self, score: u32) -> Self {
+        Suggestion::with_payload(self.word.clone(), score, self.payload.clone())
+    }
+}
+
+impl<P> Clone for Suggestion<P> {
+    fn clone(&self) -> Self {
+        self.with_score(self.score)
     }
 }
 
-impl PartialEq for Suggestion {
+impl<P> PartialEq for Suggestion<P> {
     fn eq(&self, other: &Self) -> bool {
-        self.score == self.score && self.word == other.word 
+        self.score == other.score && self.word == other.word
     }
 }
 
-impl PartialOrd for Suggestion {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.score
-            .cmp(&other.score)
-            .then_with(|| self.word.cmp(&other.word)))
+impl<P> Eq for Suggestion<P> {}
+
+impl<P> Hash for Suggestion<P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.word.hash(state);
+        self.score.hash(state);
     }
 }
 
+impl<P> Ord for Suggestion<P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .cmp(&other.score)
+            .then_with(|| self.word.cmp(&other.word))
+    }
+}
 
+impl<P> PartialOrd for Suggestion<P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
 #[cfg(test)]
 mod tests {
 
-    use crate::{naive::NaiveAutoComplete, trie::Trie};
+    use std::{collections::HashMap, sync::Arc, thread};
+
+    use crate::{naive::NaiveAutoComplete, trie::{Trie, TrieBuilder}, AutoCompletor};
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
     #[test]
     fn trie_vs_naive_test() {
         let data = [
@@ -90,4 +160,144 @@ mod tests {
 
     }
 
+    #[test]
+    fn trie_vs_naive_limit_test() {
+        let data = [
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 2),
+            ("aba", 3),
+            ("acas", 4),
+            ("caa", 5),
+            ("cascasin", 3),
+            ("cacs", 3),
+            ("cdacs", 3),
+            ("cabin", 7),
+            ("cable", 1),
+        ];
+        let prefixes = ["", "c", "a", "ca", "car", "cd", "x"];
+        let naive = NaiveAutoComplete::new(&data);
+        for &max_suggestions in [1, 3, 8].iter() {
+            let trie = TrieBuilder::new().max_suggestions(max_suggestions).build(&data);
+            assert_eq!(trie.suggestions("c").len(), max_suggestions);
+            for &prefix in prefixes.iter() {
+                for limit in 0..16 {
+                    assert_eq!(
+                        trie.suggestions_with_limit(prefix, limit),
+                        naive.suggestions_with_limit(prefix, limit),
+                        "\nPrefix: {}, limit: {}, max_suggestions: {}",
+                        prefix,
+                        limit,
+                        max_suggestions
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trie_vs_naive_multi_script_test() {
+        let mut rng = Rng(0x5eed_1234_abcd_ef01);
+        for _ in 0..5 {
+            let corpus = multi_script_corpus(&mut rng, 300);
+            let data = as_data(&corpus);
+            let prefixes = prefixes(&data, usize::MAX);
+            let mut trie = Trie::new(&data);
+            let mut naive = NaiveAutoComplete::new(&data);
+            let assert_all = |trie: &Trie, naive: &NaiveAutoComplete| {
+                assert_same(
+                    &prefixes,
+                    &[7],
+                    |prefix, limit| trie.suggestions_with_limit(prefix, limit),
+                    |prefix, limit| naive.suggestions_with_limit(prefix, limit),
+                    "",
+                )
+            };
+            assert_all(&trie, &naive);
+
+            let mut remaining = HashMap::new();
+            data.iter().for_each(|(word, score)| {
+                remaining.insert(*word, *score);
+            });
+            for (word, score) in data.iter() {
+                match rng.next(4) {
+                    0 | 1 => {
+                        assert_eq!(trie.remove(word).is_some(), remaining.remove(word).is_some());
+                    }
+                    2 if remaining.contains_key(word) => {
+                        let score = score + rng.next(20) as u32;
+                        trie.upsert(word, score);
+                        remaining.insert(*word, score);
+                    }
+                    _ => {}
+                }
+            }
+            let remaining: Vec<(&str, u32)> = remaining.into_iter().collect();
+            naive = NaiveAutoComplete::new(&remaining);
+            assert_all(&trie, &naive);
+            assert_eq!(Trie::new(&remaining), trie);
+        }
+    }
+
+    #[test]
+    fn shared_across_threads_test() {
+        let data = [("car", 1), ("carpet", 2), ("carpenter", 3), ("cocoon", 5)];
+        let completors: Vec<Arc<dyn AutoCompletor>> = vec![
+            Arc::new(Trie::new(&data)),
+            Arc::new(NaiveAutoComplete::new(&data)),
+        ];
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let completor = completors[i % completors.len()].clone();
+                thread::spawn(move || completor.suggestions("car"))
+            })
+            .collect();
+        for handle in handles {
+            let words: Vec<String> = handle
+                .join()
+                .unwrap()
+                .iter()
+                .map(|s| (*s.word).clone())
+                .collect();
+            assert_eq!(words, vec!["carpenter", "carpet", "car"]);
+        }
+    }
+
+    #[test]
+    fn trie_vs_naive_upsert_test() {
+        let data = [
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 2),
+            ("carpet", 7),
+            ("caa", 5),
+            ("cascasin", 3),
+            ("cacs", 3),
+            ("cain", 1),
+            ("cdacs", 3),
+        ];
+        let prefixes = ["c", "ca", "car", "carp", "cd", "co"];
+        let mut trie = Trie::new(&data);
+        let mut naive = NaiveAutoComplete::new(&data);
+        let assert_same = |trie: &Trie, naive: &NaiveAutoComplete| {
+            prefixes.iter().for_each(|&prefix| {
+                assert_eq!(trie.suggestions(prefix), naive.suggestions(prefix), "\nPrefix: {}", prefix)
+            })
+        };
+        assert_same(&trie, &naive);
+
+        assert_eq!(trie.upsert("cocoon", 1), naive.upsert("cocoon", 1));
+        assert_eq!(trie.upsert("cab", 4), naive.upsert("cab", 4));
+        assert_same(&trie, &naive);
+
+        assert_eq!(trie.increment_score("car", 10), naive.increment_score("car", 10));
+        assert_eq!(trie.increment_score("cdacs", 1), naive.increment_score("cdacs", 1));
+        assert_eq!(trie.increment_score("carp", 1), naive.increment_score("carp", 1));
+        assert_same(&trie, &naive);
+    }
+
 }
\ No newline at end of file
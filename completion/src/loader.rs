@@ -0,0 +1,567 @@
+//! The loader module. This module reads the (word, score) entries of a dictionary, e.g.
+//! `Loader::new(Format::Csv).header().word("term").score("frequency").load_file("words.csv.gz")`.
+//!
+//! The supported formats are:
+//! - `Lines`: one word per line (without a score)
+//! - `Tsv` and `Csv`: the word and the score are columns, picked by index or (with a header) by name
+//! - `JsonLines`: one JSON object per line, the word and the score are fields of the object
+//!
+//! The words are trimmed (in every format). Empty lines are skipped, any other line that can't be
+//! read is reported as a `LoadError` with its line number.
+
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Split};
+use std::path::Path;
+
+/// The format of a dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One word per line
+    Lines,
+    /// Tab separated columns
+    Tsv,
+    /// Comma separated columns, a column can be quoted (`"a, b"`, with `""` for a quote)
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// A column (by index or by header name) or the name of a JSON field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Index(usize),
+    Name(String),
+}
+
+impl From<usize> for Field {
+    fn from(index: usize) -> Self {
+        Field::Index(index)
+    }
+}
+
+impl From<&str> for Field {
+    fn from(name: &str) -> Self {
+        Field::Name(name.to_string())
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Index(index) => write!(f, "column {}", index),
+            Field::Name(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// Why a line could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Malformed {
+    /// The word or the score is missing (or the word is empty)
+    MissingField(Field),
+    /// The score is not an integer between 0 and `u32::MAX`
+    InvalidScore(String),
+    /// The line is not a JSON object
+    InvalidJson(String),
+    /// A quoted CSV column is not closed
+    UnterminatedQuote,
+    /// The field can't be used with the format, e.g. a name without a header
+    InvalidField(Field),
+    /// The line is not valid UTF-8
+    InvalidUtf8,
+}
+
+impl Display for Malformed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Malformed::MissingField(field) => write!(f, "missing {}", field),
+            Malformed::InvalidScore(score) => write!(f, "invalid score {}", score),
+            Malformed::InvalidJson(error) => write!(f, "invalid json ({})", error),
+            Malformed::UnterminatedQuote => write!(f, "unterminated quote"),
+            Malformed::InvalidField(field) => write!(f, "{} is not available", field),
+            Malformed::InvalidUtf8 => write!(f, "invalid utf-8"),
+        }
+    }
+}
+
+/// The error returned when a dictionary can't be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The (1 based) line that could not be read
+    Malformed { line: usize, reason: Malformed },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => e.fmt(f),
+            LoadError::Malformed { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Malformed { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<LoadError> for io::Error {
+    fn from(e: LoadError) -> Self {
+        match e {
+            LoadError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Reads dictionaries of the given format.
+/// By default the word is the first column (the "word" field for JSON) and the score is the second
+/// column (the "score" field for JSON), the words of a `Lines` dictionary (or with `no_score`) have a
+/// score of 0.
+#[derive(Debug, Clone)]
+pub struct Loader {
+    format: Format,
+    word: Field,
+    score: Option<Field>,
+    score_last: bool,
+    header: bool,
+    gzip: bool,
+}
+
+impl Loader {
+    pub fn new(format: Format) -> Self {
+        let (word, score) = match format {
+            Format::Lines => (Field::Index(0), None),
+            Format::Tsv | Format::Csv => (Field::Index(0), Some(Field::Index(1))),
+            Format::JsonLines => (Field::from("word"), Some(Field::from("score"))),
+        };
+        Loader {
+            format,
+            word,
+            score,
+            score_last: false,
+            header: false,
+            gzip: false,
+        }
+    }
+
+    /// The column (or JSON field) of the word
+    pub fn word(mut self, field: impl Into<Field>) -> Self {
+        self.word = field.into();
+        self
+    }
+
+    /// The column (or JSON field) of the score
+    pub fn score(mut self, field: impl Into<Field>) -> Self {
+        self.score = Some(field.into());
+        self
+    }
+
+    /// Every word has a score of 0
+    pub fn no_score(mut self) -> Self {
+        self.score = None;
+        self.score_last = false;
+        self
+    }
+
+    /// The score of a TSV/CSV line is its last column and the word is the rest of the line, i.e.
+    /// the other columns joined by the separator (e.g. "word\twith\ttabs\t5" is "word\twith\ttabs").
+    /// The word and the score columns are ignored.
+    pub fn score_last(mut self) -> Self {
+        self.score_last = true;
+        self
+    }
+
+    /// The first line of a TSV/CSV dictionary names the columns
+    pub fn header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// The input is gzip compressed
+    pub fn gzip(mut self) -> Self {
+        self.gzip = true;
+        self
+    }
+
+    /// Loads the dictionary at `path`, a path ending with ".gz" is decompressed
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<Vec<(String, u32)>, LoadError> {
+        let path = path.as_ref();
+        let mut loader = self.clone();
+        if path.extension() == Some("gz".as_ref()) {
+            loader.gzip = true;
+        }
+        loader.load(File::open(path)?)
+    }
+
+    /// Loads the whole dictionary, it fails on the first line that can't be read
+    pub fn load(&self, reader: impl Read) -> Result<Vec<(String, u32)>, LoadError> {
+        self.entries(reader).collect()
+    }
+
+    /// Reads the entries one by one, e.g. to skip (and log) the lines that can't be read
+    pub fn entries<'a>(&self, reader: impl Read + 'a) -> Entries<'a> {
+        let reader: Box<dyn Read + 'a> = if self.gzip {
+            Box::new(GzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+        Entries {
+            loader: self.clone(),
+            lines: BufReader::new(reader).split(b'\n'),
+            line: 0,
+            columns: None,
+        }
+    }
+}
+
+/// An iterator over the entries of a dictionary, see `Loader::entries`
+pub struct Entries<'a> {
+    loader: Loader,
+    lines: Split<BufReader<Box<dyn Read + 'a>>>,
+    line: usize,
+    /// The names of the columns (from the header)
+    columns: Option<Vec<String>>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<(String, u32), LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let bytes = match self.lines.next()? {
+                Ok(bytes) => bytes,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line += 1;
+            // the line is skipped, so the next entries can still be read
+            let text = match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => {
+                    return Some(Err(LoadError::Malformed {
+                        line: self.line,
+                        reason: Malformed::InvalidUtf8,
+                    }))
+                }
+            };
+            let text = text.strip_suffix('\r').unwrap_or(&text);
+            // a byte order mark would become part of the first word
+            let text = if self.line == 1 {
+                text.trim_start_matches('\u{feff}')
+            } else {
+                text
+            };
+            if text.trim().is_empty() {
+                continue;
+            }
+            let entry = match self.loader.format {
+                Format::Lines => Ok(Some((text.trim().to_string(), 0))),
+                Format::Tsv => self.columns(text.split('\t').map(|c| c.to_string()).collect()),
+                Format::Csv => match split_csv(text) {
+                    Some(columns) => self.columns(columns),
+                    None => Err(Malformed::UnterminatedQuote),
+                },
+                Format::JsonLines => self.json(text),
+            };
+            match entry {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                // the header
+                Ok(None) => continue,
+                Err(reason) => {
+                    return Some(Err(LoadError::Malformed {
+                        line: self.line,
+                        reason,
+                    }))
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Entries<'a> {
+    /// Reads the entry from the columns of a TSV/CSV line, returns `None` for the header
+    fn columns(&mut self, columns: Vec<String>) -> Result<Option<(String, u32)>, Malformed> {
+        if self.loader.header && self.columns.is_none() {
+            self.columns = Some(columns.iter().map(|c| c.trim().to_string()).collect());
+            return Ok(None);
+        }
+        if self.loader.score_last {
+            let separator = if self.loader.format == Format::Csv { "," } else { "\t" };
+            return match columns.split_last() {
+                Some((score, words)) if !words.is_empty() => {
+                    let word = word(&words.join(separator), &Field::Index(0))?;
+                    Ok(Some((word, parse_score(score.trim())?)))
+                }
+                _ => Err(Malformed::MissingField(Field::Index(1))),
+            };
+        }
+        let column = |field: &Field| -> Result<&str, Malformed> {
+            let index = match field {
+                Field::Index(index) => *index,
+                Field::Name(name) => self
+                    .columns
+                    .as_ref()
+                    .and_then(|names| names.iter().position(|n| n == name))
+                    .ok_or_else(|| Malformed::InvalidField(field.clone()))?,
+            };
+            columns
+                .get(index)
+                .map(|c| &c[..])
+                .ok_or_else(|| Malformed::MissingField(field.clone()))
+        };
+        let word = word(column(&self.loader.word)?, &self.loader.word)?;
+        let score = match &self.loader.score {
+            Some(field) => parse_score(column(field)?.trim())?,
+            None => 0,
+        };
+        Ok(Some((word, score)))
+    }
+
+    fn json(&self, text: &str) -> Result<Option<(String, u32)>, Malformed> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| Malformed::InvalidJson(e.to_string()))?;
+        if !value.is_object() {
+            return Err(Malformed::InvalidJson("not an object".to_string()));
+        }
+        let field = |field: &Field| -> Result<&Value, Malformed> {
+            match field {
+                Field::Name(name) => value
+                    .get(name)
+                    .ok_or_else(|| Malformed::MissingField(field.clone())),
+                Field::Index(_) => Err(Malformed::InvalidField(field.clone())),
+            }
+        };
+        let word = match field(&self.loader.word)?.as_str() {
+            Some(text) => word(text, &self.loader.word)?,
+            None => return Err(Malformed::MissingField(self.loader.word.clone())),
+        };
+        let score = match &self.loader.score {
+            Some(name) => match field(name)? {
+                Value::Number(n) => n
+                    .as_u64()
+                    .filter(|n| *n <= u32::MAX as u64)
+                    .ok_or_else(|| Malformed::InvalidScore(n.to_string()))? as u32,
+                Value::String(s) => parse_score(s.trim())?,
+                other => return Err(Malformed::InvalidScore(other.to_string())),
+            },
+            None => 0,
+        };
+        Ok(Some((word, score)))
+    }
+}
+
+/// Returns the trimmed word, an empty word is missing
+fn word(text: &str, field: &Field) -> Result<String, Malformed> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(Malformed::MissingField(field.clone()));
+    }
+    Ok(text.to_string())
+}
+
+fn parse_score(text: &str) -> Result<u32, Malformed> {
+    text.parse::<u32>()
+        .map_err(|_| Malformed::InvalidScore(format!("'{}'", text)))
+}
+
+/// Splits a CSV line into its columns, returns `None` if a quote is not closed
+fn split_csv(line: &str) -> Option<Vec<String>> {
+    let mut columns = vec![];
+    let mut column = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    column.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if column.is_empty() => quoted = true,
+            ',' if !quoted => columns.push(std::mem::take(&mut column)),
+            ch => column.push(ch),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    columns.push(column);
+    Some(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_csv, Field, Format, LoadError, Loader, Malformed};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{ErrorKind, Write};
+
+    fn load(loader: Loader, input: &str) -> Result<Vec<(String, u32)>, LoadError> {
+        loader.load(input.as_bytes())
+    }
+
+    fn entries(input: &[(&str, u32)]) -> Vec<(String, u32)> {
+        input.iter().map(|(w, s)| (w.to_string(), *s)).collect()
+    }
+
+    fn malformed(result: Result<Vec<(String, u32)>, LoadError>) -> (usize, Malformed) {
+        match result {
+            Err(LoadError::Malformed { line, reason }) => (line, reason),
+            other => panic!("expected a malformed line, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lines_test() {
+        let input = "\u{feff}car\r\n\n carpet \nnew york\n";
+        assert_eq!(
+            load(Loader::new(Format::Lines), input).unwrap(),
+            entries(&[("car", 0), ("carpet", 0), ("new york", 0)])
+        );
+    }
+
+    #[test]
+    fn tsv_test() {
+        let input = "car\t1\nnew york\t 4\n\n carpet \t2\textra\n";
+        assert_eq!(
+            load(Loader::new(Format::Tsv), input).unwrap(),
+            entries(&[("car", 1), ("new york", 4), ("carpet", 2)])
+        );
+        let input = "word\twith\ttabs\t5\n carpet\t2\n";
+        assert_eq!(
+            load(Loader::new(Format::Tsv).score_last(), input).unwrap(),
+            entries(&[("word\twith\ttabs", 5), ("carpet", 2)])
+        );
+        assert_eq!(
+            malformed(load(Loader::new(Format::Tsv).score_last(), "car\t1\ncarpet\n")),
+            (2, Malformed::MissingField(Field::Index(1)))
+        );
+        let input = "score\tword\n3\tcarpenter\n";
+        let loader = Loader::new(Format::Tsv).header().word("word").score("score");
+        assert_eq!(load(loader, input).unwrap(), entries(&[("carpenter", 3)]));
+        let loader = Loader::new(Format::Tsv).word(1).score(0);
+        assert_eq!(load(loader, "3\tcarpenter").unwrap(), entries(&[("carpenter", 3)]));
+        assert_eq!(
+            malformed(load(Loader::new(Format::Tsv), "car\t1\ncarpet\n")),
+            (2, Malformed::MissingField(Field::Index(1)))
+        );
+        assert_eq!(
+            malformed(load(Loader::new(Format::Tsv), "car\t-1\n")),
+            (1, Malformed::InvalidScore("'-1'".to_string()))
+        );
+        assert_eq!(
+            malformed(load(Loader::new(Format::Tsv), " \t1\n")),
+            (1, Malformed::MissingField(Field::Index(0)))
+        );
+        let loader = Loader::new(Format::Tsv).word("word");
+        assert_eq!(
+            malformed(load(loader, "car\t1\n")),
+            (1, Malformed::InvalidField(Field::from("word")))
+        );
+    }
+
+    #[test]
+    fn csv_test() {
+        let input = "term,frequency\n\"new york, ny\",4\ncar,1\n\"say \"\"hi\"\"\",2\n";
+        let loader = Loader::new(Format::Csv)
+            .header()
+            .word("term")
+            .score("frequency");
+        assert_eq!(
+            load(loader, input).unwrap(),
+            entries(&[("new york, ny", 4), ("car", 1), ("say \"hi\"", 2)])
+        );
+        assert_eq!(
+            malformed(load(Loader::new(Format::Csv), "car,1\n\"car,1\n")),
+            (2, Malformed::UnterminatedQuote)
+        );
+        assert_eq!(
+            malformed(load(Loader::new(Format::Csv).header().word("word"), "term\ncar\n")),
+            (2, Malformed::InvalidField(Field::from("word")))
+        );
+        assert_eq!(split_csv("a,,\"\""), Some(vec!["a".into(), "".into(), "".into()]));
+    }
+
+    #[test]
+    fn json_lines_test() {
+        let input = "{\"word\": \"car\", \"score\": 1}\n{\"score\": \"2\", \"word\": \"carpet\"}\n";
+        assert_eq!(
+            load(Loader::new(Format::JsonLines), input).unwrap(),
+            entries(&[("car", 1), ("carpet", 2)])
+        );
+        let loader = Loader::new(Format::JsonLines).word("q").no_score();
+        assert_eq!(load(loader, "{\"q\": \"日本語\"}").unwrap(), entries(&[("日本語", 0)]));
+        let invalid = |input: &str| malformed(load(Loader::new(Format::JsonLines), input)).1;
+        assert!(matches!(invalid("{\"word\": \"car\""), Malformed::InvalidJson(_)));
+        assert!(matches!(invalid("[\"car\", 1]"), Malformed::InvalidJson(_)));
+        assert_eq!(
+            invalid("{\"word\": \"car\"}"),
+            Malformed::MissingField(Field::from("score"))
+        );
+        assert_eq!(
+            invalid("{\"word\": \"car\", \"score\": 1.5}"),
+            Malformed::InvalidScore("1.5".to_string())
+        );
+        assert_eq!(
+            invalid("{\"word\": 1, \"score\": 1}"),
+            Malformed::MissingField(Field::from("word"))
+        );
+    }
+
+    #[test]
+    fn gzip_test() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"car\t1\ncarpet\t2\n").unwrap();
+        let bytes = encoder.finish().unwrap();
+        let loader = Loader::new(Format::Tsv).gzip();
+        assert_eq!(
+            loader.load(&bytes[..]).unwrap(),
+            entries(&[("car", 1), ("carpet", 2)])
+        );
+        let path = std::env::temp_dir().join(format!("loader_test_{}.tsv.gz", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let loaded = Loader::new(Format::Tsv).load_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), entries(&[("car", 1), ("carpet", 2)]));
+        // not gzip compressed
+        let error = Loader::new(Format::Tsv).gzip().load(&b"car\t1\n"[..]).unwrap_err();
+        assert!(matches!(error, LoadError::Io(_)));
+    }
+
+    #[test]
+    fn entries_test() {
+        let input = "car\t1\ncarpet\tmany\ncarpenter\t3\n";
+        let (entries, errors): (Vec<_>, Vec<_>) = Loader::new(Format::Tsv)
+            .entries(input.as_bytes())
+            .partition(|e| e.is_ok());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(errors.len(), 1);
+        let error: std::io::Error = errors.into_iter().next().unwrap().unwrap_err().into();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 2: invalid score 'many'");
+        // the lines after an invalid utf-8 line are read
+        let input = &b"car\n\xff\xfe\ncarpet\n"[..];
+        let entries: Vec<_> = Loader::new(Format::Lines).entries(input).collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].as_ref().unwrap(), &("car".to_string(), 0));
+        assert_eq!(entries[1].as_ref().unwrap_err().to_string(), "line 2: invalid utf-8");
+        assert_eq!(entries[2].as_ref().unwrap(), &("carpet".to_string(), 0));
+    }
+}
@@ -1,50 +1,98 @@
 //! The naive auto complete suggestion module
 
 
-use crate::AutoCompletor;
+use crate::normalize::{Identity, Normalizer};
+use crate::{AutoCompletor, DEFAULT_MAX_SUGGESTIONS};
 use crate::Suggestion;
-use std::collections::BTreeSet;
-use std::rc::Rc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct NaiveAutoComplete {
-    suggestions: BTreeSet<Rc<Suggestion>>,
+    pub(crate) suggestions: BTreeSet<Suggestion>,
+    /// The suggestions by their (normalized) key
+    pub(crate) words: HashMap<String, Suggestion>,
+    pub(crate) normalizer: Arc<dyn Normalizer>,
 }
 
 impl NaiveAutoComplete {
     pub fn new(suggestions: &[(&str, u32)]) -> Self {
-        let s = suggestions
-            .iter()
-            .map(|(sug, sco)| Rc::new(Suggestion::new(Rc::new((**sug).into()), *sco)))
-            .collect::<BTreeSet<Rc<Suggestion>>>();
-        NaiveAutoComplete { suggestions: s }
+        NaiveAutoComplete::with_normalizer(suggestions, Identity)
+    }
+
+    /// Initializes the auto complete with a `Normalizer` applied to the words and the prefixes
+    pub(crate) fn with_normalizer(
+        suggestions: &[(&str, u32)],
+        normalizer: impl Normalizer + 'static,
+    ) -> Self {
+        let mut naive = NaiveAutoComplete {
+            suggestions: BTreeSet::new(),
+            words: HashMap::new(),
+            normalizer: Arc::new(normalizer),
+        };
+        suggestions.iter().for_each(|(sug, sco)| {
+            naive.upsert(sug, *sco);
+        });
+        naive
+    }
+
+    /// Inserts the given `word` with the `score`, or replaces the score if the word is already present.
+    /// Returns the previous suggestion (if any).
+    pub fn upsert(&mut self, word: &str, score: u32) -> Option<Suggestion> {
+        let key = self.normalizer.normalize(word).into_owned();
+        let suggestion = Suggestion::new(Arc::new(String::from(word)), score);
+        let previous = self.words.insert(key, suggestion.clone());
+        if let Some(previous) = &previous {
+            self.suggestions.remove(previous);
+        }
+        self.suggestions.insert(suggestion);
+        previous
+    }
+
+    /// Increments the score of the given `word` by `delta` (saturating at `u32::MAX`).
+    /// Returns the updated suggestion, or `None` if the word is not present.
+    pub fn increment_score(&mut self, word: &str, delta: u32) -> Option<Suggestion> {
+        let key = self.normalizer.normalize(word).into_owned();
+        let current = self.words.get(&key)?.clone();
+        self.upsert(&current.word, current.score.saturating_add(delta));
+        self.words.get(&key).cloned()
     }
 
-    pub fn suggestions(&self, prefix: &str) -> Vec<Rc<Suggestion>> {
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, DEFAULT_MAX_SUGGESTIONS)
+    }
+
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = self.normalizer.normalize(prefix);
         let matching_suggestions = self
             .suggestions
             .iter()
             .rev()
-            .filter(|suggestion| suggestion.word.starts_with(prefix))
-            .take(5)
-            .map(|s| s.clone())
-            .collect::<Vec<Rc<Suggestion>>>();
+            .filter(|suggestion| {
+                self.normalizer
+                    .normalize(&suggestion.word)
+                    .starts_with(&*prefix)
+            })
+            .take(limit)
+            .cloned()
+            .collect::<Vec<Suggestion>>();
         matching_suggestions
     }
-
-    
 }
 
 impl AutoCompletor for NaiveAutoComplete {
-    fn suggestions(&self, prefix: &str) -> Vec<Rc<Suggestion>> { 
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> { 
         self.suggestions(prefix)
     }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::rc::Rc;
-use crate::Suggestion;
+    use crate::Suggestion;
 
     use super::NaiveAutoComplete;
 
@@ -82,7 +130,7 @@ use crate::Suggestion;
         );
     }
 
-    fn suggestions_as_str<'a>(suggestions: &'a [Rc<Suggestion>]) -> Vec<&'a str> {
+    fn suggestions_as_str(suggestions: &[Suggestion]) -> Vec<&str> {
         suggestions
             .iter()
             .map(|b| &b.word)
@@ -0,0 +1,258 @@
+//! The namespace module.
+//! A `TrieSet` keeps a shared base `Trie` (e.g. a global vocabulary) and a small `Trie` per tenant.
+//! The suggestions of a tenant (see `Namespace`) merge its own words, boosted by the tenant's
+//! boost, with the base words. A tenant word that is also a base word shares its string with
+//! the base.
+
+use crate::trie::Trie;
+use crate::{AutoCompletor, Suggestion};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The per tenant tries on top of a shared base `Trie`
+#[derive(Debug)]
+pub struct TrieSet {
+    base: Trie,
+    tenants: HashMap<String, Tenant>,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    trie: Trie,
+    /// Added to the scores of the tenant's words
+    boost: u32,
+}
+
+impl TrieSet {
+    /// Initializes the set with the `base` words shared by all the tenants.
+    /// The tenant tries use the configuration and the normalizer of the base.
+    pub fn new(base: Trie) -> Self {
+        TrieSet {
+            base,
+            tenants: HashMap::new(),
+        }
+    }
+
+    pub fn base(&self) -> &Trie {
+        &self.base
+    }
+
+    pub fn base_mut(&mut self) -> &mut Trie {
+        &mut self.base
+    }
+
+    /// Adds the `tenant` (without any words), or updates its boost if it is already present
+    pub fn add_tenant(&mut self, tenant: &str, boost: u32) {
+        match self.tenants.get_mut(tenant) {
+            Some(existing) => existing.boost = boost,
+            None => {
+                let trie = self.base.empty_like();
+                self.tenants.insert(tenant.to_string(), Tenant { trie, boost });
+            }
+        }
+    }
+
+    /// Removes the `tenant` and all of its words, returns `false` if it is not present
+    pub fn remove_tenant(&mut self, tenant: &str) -> bool {
+        self.tenants.remove(tenant).is_some()
+    }
+
+    /// Returns the names of the tenants (in no particular order)
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(|tenant| &tenant[..])
+    }
+
+    /// Inserts the given `word` with the `score` for the `tenant` (added with a boost of 0 if it is
+    /// not present), or replaces the score if the tenant already has the word.
+    /// Returns the previous suggestion of the tenant (if any).
+    pub fn upsert(&mut self, tenant: &str, word: &str, score: u32) -> Option<Suggestion> {
+        if !self.tenants.contains_key(tenant) {
+            self.add_tenant(tenant, 0);
+        }
+        let shared = self
+            .base
+            .get(word)
+            .map(|suggestion| suggestion.word)
+            .filter(|shared| **shared == word)
+            .unwrap_or_else(|| Arc::new(word.to_string()));
+        let tenant = self.tenants.get_mut(tenant).unwrap();
+        tenant.trie.upsert_shared(shared, score)
+    }
+
+    /// Removes the given `word` of the `tenant` (the base words are not changed).
+    /// Returns the removed suggestion, if the tenant had the word.
+    pub fn remove(&mut self, tenant: &str, word: &str) -> Option<Suggestion> {
+        self.tenants.get_mut(tenant)?.trie.remove(word)
+    }
+
+    /// Returns the view of the `tenant`, an unknown tenant only gets the base suggestions
+    pub fn namespace<'a>(&'a self, tenant: &str) -> Namespace<'a> {
+        Namespace {
+            base: &self.base,
+            tenant: self.tenants.get(tenant),
+        }
+    }
+}
+
+/// The words of a tenant merged with the base words, see `TrieSet::namespace`
+#[derive(Debug, Clone, Copy)]
+pub struct Namespace<'a> {
+    base: &'a Trie,
+    tenant: Option<&'a Tenant>,
+}
+
+impl Namespace<'_> {
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, self.base.config().max_suggestions)
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`.
+    /// A word of both the tenant and the base is suggested once, with the higher of its
+    /// (boosted) tenant score and its base score.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let base = self.base.suggestions_with_limit(prefix, limit);
+        let tenant = match self.tenant {
+            Some(tenant) => tenant,
+            None => return base,
+        };
+        // the top `limit` of both sides contain the top `limit` of the merged words,
+        // since the boost keeps the order of the tenant words
+        let boosted = tenant
+            .trie
+            .suggestions_with_limit(prefix, limit)
+            .into_iter()
+            .map(|s| s.with_score(s.score().saturating_add(tenant.boost)));
+        let mut merged: HashMap<Vec<char>, Suggestion> = HashMap::new();
+        for suggestion in boosted.chain(base) {
+            let key = self.base.key(&suggestion.word);
+            match merged.get(&key) {
+                Some(existing) if *existing >= suggestion => {}
+                _ => {
+                    merged.insert(key, suggestion);
+                }
+            }
+        }
+        let mut suggestions: Vec<Suggestion> = merged.into_values().collect();
+        suggestions.sort_by(|a, b| b.cmp(a));
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+impl AutoCompletor for Namespace<'_> {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions(prefix)
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrieSet;
+    use crate::normalize::StandardNormalizer;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::{Trie, TrieBuilder};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn words(set: &TrieSet, tenant: &str, prefix: &str) -> Vec<(String, u32)> {
+        set.namespace(tenant)
+            .suggestions(prefix)
+            .iter()
+            .map(|s| ((*s.word).clone(), s.score()))
+            .collect()
+    }
+
+    #[test]
+    fn trie_set_test() {
+        let base = Trie::new(&[("car", 1), ("carpet", 4), ("cart", 3), ("cocoon", 5)]);
+        let mut set = TrieSet::new(base);
+        set.add_tenant("acme", 10);
+        set.upsert("acme", "carburetor", 1);
+        set.upsert("acme", "car", 2);
+        set.upsert("globex", "carrot", 9);
+        assert_eq!(
+            words(&set, "acme", "car"),
+            vec![
+                ("car".to_string(), 12),
+                ("carburetor".to_string(), 11),
+                ("carpet".to_string(), 4),
+                ("cart".to_string(), 3),
+            ]
+        );
+        assert_eq!(words(&set, "globex", "car")[0], ("carrot".to_string(), 9));
+        assert_eq!(words(&set, "initech", "car")[0], ("carpet".to_string(), 4));
+        assert_eq!(words(&set, "acme", "carb"), vec![("carburetor".to_string(), 11)]);
+        // the tenant words are only suggested for their tenant
+        assert!(words(&set, "globex", "carb").is_empty());
+        // the common words share their string with the base
+        let acme = set.namespace("acme").suggestions("car");
+        let base = set.base().suggestions("car");
+        let car = |suggestions: &[crate::Suggestion]| {
+            suggestions.iter().find(|s| *s.word == "car").unwrap().word.clone()
+        };
+        assert!(Arc::ptr_eq(&car(&acme), &car(&base)));
+        // a lower boost lets the base score win
+        set.add_tenant("acme", 0);
+        assert_eq!(words(&set, "acme", "car")[0], ("carpet".to_string(), 4));
+        assert_eq!(words(&set, "acme", "car").len(), 4);
+        assert_eq!(set.remove("acme", "car").map(|s| s.score()), Some(2));
+        assert_eq!(words(&set, "acme", "car")[2], ("carburetor".to_string(), 1));
+        assert!(set.remove_tenant("globex"));
+        assert!(!set.remove_tenant("globex"));
+        assert_eq!(set.tenants().collect::<Vec<&str>>(), vec!["acme"]);
+    }
+
+    #[test]
+    fn trie_set_normalizer_test() {
+        let base = TrieBuilder::new()
+            .normalizer(StandardNormalizer::new().lowercase())
+            .build(&[("Paris", 3)]);
+        let mut set = TrieSet::new(base);
+        set.upsert("acme", "paris", 1);
+        set.upsert("acme", "PARK", 2);
+        // "Paris" and "paris" are the same word, suggested once with the higher score
+        assert_eq!(
+            words(&set, "acme", "PA"),
+            vec![("Paris".to_string(), 3), ("PARK".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn trie_set_vs_naive_test() {
+        let mut rng = Rng(0x7e4a_4715_0000_beef);
+        let corpus = multi_script_corpus(&mut rng, 300);
+        let data = as_data(&corpus);
+        let mut set = TrieSet::new(Trie::new(&data));
+        let mut merged: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        for (tenant, boost) in [("acme", 0), ("globex", 5), ("initech", 30)].iter() {
+            set.add_tenant(tenant, *boost);
+            let words = merged.entry(tenant.to_string()).or_default();
+            words.extend(corpus.iter().cloned());
+            // some base words with a tenant score and some words of the tenant only
+            let extra = multi_script_corpus(&mut rng, 50);
+            let common = corpus.iter().filter(|_| rng.next(5) == 0).cloned();
+            for (word, score) in common.chain(extra) {
+                set.upsert(tenant, &word, score);
+                let base = set.base().get(&word).map_or(0, |s| s.score());
+                words.insert(word, base.max(score + boost));
+            }
+        }
+        for (tenant, words) in merged.iter() {
+            let data: Vec<(&str, u32)> = words.iter().map(|(w, s)| (&w[..], *s)).collect();
+            let naive = NaiveAutoComplete::new(&data);
+            let namespace = set.namespace(tenant);
+            assert_same(
+                &prefixes(&data, 3),
+                &[1, 5, 12],
+                |prefix, limit| namespace.suggestions_with_limit(prefix, limit),
+                |prefix, limit| naive.suggestions_with_limit(prefix, limit),
+                &format!("tenant: {}", tenant),
+            );
+        }
+    }
+}
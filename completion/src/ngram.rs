@@ -0,0 +1,212 @@
+//! The ngram module.
+//! An `NgramModel` predicts the next word from the previous ones (e.g. "new york " suggests
+//! "city"), with the counts of the n-grams of a text corpus. Every n-gram (up to the `order`) is
+//! stored in a `Trie` keyed by its context (the previous words) and its next word, so the next words
+//! of a context are a subtree ranked by their counts and a partially typed next word is a prefix
+//! in it.
+
+use crate::infix::SEPARATOR;
+use crate::phrase::{self, Tokenizer, WordTokenizer};
+use crate::trie::{Trie, TrieBuilder};
+use crate::{AutoCompletor, Suggestion};
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+/// The counts of the n-grams of a text corpus, see `NgramModel::learn_lines`
+#[derive(Debug)]
+pub struct NgramModel {
+    index: Trie,
+    /// The number of words of the longest n-grams, i.e. the context is `order - 1` words
+    order: usize,
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl NgramModel {
+    /// Initializes an empty model of the given `order` (at least 1), e.g. 3 for the trigrams,
+    /// the texts are split by a `WordTokenizer`
+    pub fn new(order: usize) -> Self {
+        NgramModel::with_builder(TrieBuilder::new(), order, WordTokenizer)
+    }
+
+    /// Initializes an empty model with the configuration and the normalizer of the `builder`,
+    /// the texts are split by the `tokenizer`
+    pub fn with_builder(
+        builder: TrieBuilder,
+        order: usize,
+        tokenizer: impl Tokenizer + 'static,
+    ) -> Self {
+        NgramModel {
+            index: builder.build_empty(),
+            order: order.max(1),
+            tokenizer: Arc::new(tokenizer),
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the key of the `next` word after the `context`
+    fn key(context: &[Vec<char>], next: &[char]) -> Vec<char> {
+        let mut key = phrase::join(context);
+        key.push(SEPARATOR);
+        key.extend_from_slice(next);
+        key
+    }
+
+    /// Counts the n-grams of the `text`, i.e. every word after each of its (up to `order - 1`)
+    /// previous words. A word is suggested as it is typed the first time it is learned, e.g.
+    /// "City" after "New York City" then "new york city".
+    pub fn learn(&mut self, text: &str) {
+        // the words as typed and their normalized tokens
+        let words: Vec<&str> =
+            self.tokenizer.tokenize(text).into_iter().map(|range| &text[range]).collect();
+        let tokens: Vec<Vec<char>> = words
+            .iter()
+            .map(|word| self.index.normalizer().normalize(word).chars().collect())
+            .collect();
+        for (i, token) in tokens.iter().enumerate() {
+            let mut word = None;
+            for n in 0..self.order.min(i + 1) {
+                let key = NgramModel::key(&tokens[i - n..i], token);
+                let current = self.index.get_key(&key);
+                let count = current.as_ref().map_or(0, |s| s.score()).saturating_add(1);
+                // the n-grams of a word share its string
+                let word = word
+                    .get_or_insert_with(|| match current {
+                        Some(current) => current.word,
+                        None => Arc::new(words[i].to_string()),
+                    })
+                    .clone();
+                self.index.upsert_at(&key, Suggestion::new(word, count));
+            }
+        }
+    }
+
+    /// Counts the n-grams of a text corpus (e.g. a file), every line is a separate text
+    pub fn learn_lines(&mut self, corpus: impl BufRead) -> io::Result<()> {
+        for line in corpus.lines() {
+            self.learn(&line?);
+        }
+        Ok(())
+    }
+
+    pub fn suggestions(&self, text: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(text, self.index.config().max_suggestions)
+    }
+
+    /// Returns the (at most) `limit` most frequent next words after the `text`, scored by their
+    /// counts. After a space (or another separator) the next word is predicted, otherwise the last
+    /// word of the text is completed, e.g. "new york " and "new york c" both suggest "city".
+    /// The words after the longest context come first, the ones after the shorter contexts
+    /// (down to no context at all) fill in the rest.
+    pub fn suggestions_with_limit(&self, text: &str, limit: usize) -> Vec<Suggestion> {
+        let (tokens, complete) =
+            phrase::tokens(self.index.normalizer(), self.tokenizer.as_ref(), text);
+        let (context, partial) = match tokens.split_last() {
+            Some((last, context)) if !complete => (context, &last[..]),
+            _ => (&tokens[..], &[][..]),
+        };
+        let mut suggestions: Vec<Suggestion> = vec![];
+        for n in (0..=context.len().min(self.order - 1)).rev() {
+            if suggestions.len() >= limit {
+                break;
+            }
+            let key = NgramModel::key(&context[context.len() - n..], partial);
+            // some of them may already be suggested (after a longer context)
+            let candidates = self.index.top_suggestions(&key, limit + suggestions.len());
+            for suggestion in candidates {
+                let known = suggestions.iter().any(|s| s.word == suggestion.word);
+                if suggestions.len() < limit && !known {
+                    suggestions.push(suggestion);
+                }
+            }
+        }
+        suggestions
+    }
+}
+
+impl AutoCompletor for NgramModel {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions(prefix)
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NgramModel;
+    use crate::normalize::StandardNormalizer;
+    use crate::phrase::WordTokenizer;
+    use crate::test_support::Rng;
+    use crate::trie::TrieBuilder;
+    use std::collections::HashMap;
+
+    fn words(model: &NgramModel, text: &str, limit: usize) -> Vec<(String, u32)> {
+        model
+            .suggestions_with_limit(text, limit)
+            .iter()
+            .map(|s| ((*s.word).clone(), s.score()))
+            .collect()
+    }
+
+    #[test]
+    fn ngram_suggestions_test() {
+        let corpus = "I want to eat pizza\n\
+                      i want to go home\n\
+                      I want to go out.\n\
+                      you want to go\n\
+                      New York City\n\
+                      new york pizza\n\
+                      new York city hall\n";
+        let builder = TrieBuilder::new().normalizer(StandardNormalizer::new().lowercase());
+        let mut model = NgramModel::with_builder(builder, 3, WordTokenizer);
+        model.learn_lines(corpus.as_bytes()).unwrap();
+        let owned = |words: &[(&str, u32)]| -> Vec<(String, u32)> {
+            words.iter().map(|(w, c)| (w.to_string(), *c)).collect()
+        };
+        assert_eq!(words(&model, "i want to ", 2), owned(&[("go", 3), ("eat", 1)]));
+        assert_eq!(words(&model, "I want to g", 5), owned(&[("go", 3)]));
+        // the words are suggested as they are typed the first time
+        assert_eq!(words(&model, "new york ", 2), owned(&[("City", 2), ("pizza", 1)]));
+        assert_eq!(words(&model, "New-York c", 5), owned(&[("City", 2)]));
+        assert_eq!(words(&model, "new ", 1), owned(&[("York", 3)]));
+        assert_eq!(words(&model, "new york pi", 5), owned(&[("pizza", 1)]));
+        // no "want to p" nor "to p", backs off to the words
+        assert_eq!(words(&model, "want to p", 5), owned(&[("pizza", 2)]));
+        assert_eq!(words(&model, "hall ", 1), owned(&[("want", 4)]));
+        assert_eq!(words(&model, "", 3), owned(&[("want", 4), ("to", 4), ("go", 3)]));
+        // a longer context backs off to the last `order - 1` words
+        assert_eq!(words(&model, "you want to ", 1), words(&model, "want to ", 1));
+        assert_eq!(words(&model, "x", 5), vec![]);
+    }
+
+    #[test]
+    fn ngram_counts_test() {
+        // a small vocabulary, so the contexts repeat
+        let vocabulary = ["a", "b", "c", "d"];
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut model = NgramModel::new(3);
+        let mut counts: HashMap<(String, String), u32> = HashMap::new();
+        for _ in 0..200 {
+            let line: Vec<&str> = (0..1 + rng.next(6)).map(|_| vocabulary[rng.next(4)]).collect();
+            model.learn(&line.join(" "));
+            for window in line.windows(3) {
+                let context = format!("{} {}", window[0], window[1]);
+                *counts.entry((context, window[2].to_string())).or_default() += 1;
+            }
+        }
+        let mut by_context: HashMap<String, Vec<(String, u32)>> = HashMap::new();
+        for ((context, word), count) in counts {
+            by_context.entry(context).or_default().push((word, count));
+        }
+        for (context, mut expected) in by_context {
+            expected.sort_by(|a, b| (b.1, &b.0).cmp(&(a.1, &a.0)));
+            let actual = words(&model, &format!("{} ", context), expected.len());
+            assert_eq!(actual, expected, "\nContext: {}", context);
+        }
+    }
+}
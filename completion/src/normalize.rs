@@ -0,0 +1,150 @@
+//! The normalize module.
+//! A `Normalizer` maps a word (or a prefix) to the key used for matching, e.g. "Café" to "cafe".
+//! The same normalizer is applied when the words are inserted and when the prefixes are queried,
+//! the suggestions still return the original word.
+
+use std::{borrow::Cow, fmt::Debug};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Maps the input to the key that is used for matching
+pub trait Normalizer: Debug + Send + Sync {
+    fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str>;
+}
+
+/// The default normalizer, it matches the input as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Identity;
+
+impl Normalizer for Identity {
+    fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(input)
+    }
+}
+
+/// The unicode normalization forms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition, e.g. "e\u{301}" becomes "é"
+    Nfc,
+    /// Compatibility composition, e.g. "ﬁ" becomes "fi" (in addition to NFC)
+    Nfkc,
+}
+
+/// A configurable normalizer, e.g.
+/// `StandardNormalizer::new().lowercase().form(UnicodeForm::Nfkc).fold_accents()`
+/// matches "Café", "cafe\u{301}" and "CAFE" with the prefix "caf".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StandardNormalizer {
+    lowercase: bool,
+    form: Option<UnicodeForm>,
+    fold_accents: bool,
+}
+
+impl StandardNormalizer {
+    pub fn new() -> Self {
+        StandardNormalizer::default()
+    }
+
+    /// Matches case insensitively
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+
+    /// Matches composed and decomposed forms of a word
+    pub fn form(mut self, form: UnicodeForm) -> Self {
+        self.form = Some(form);
+        self
+    }
+
+    /// Strips the accents (combining marks), e.g. "é" becomes "e"
+    pub fn fold_accents(mut self) -> Self {
+        self.fold_accents = true;
+        self
+    }
+}
+
+impl Normalizer for StandardNormalizer {
+    fn normalize<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        let mut output: Cow<'a, str> = Cow::Borrowed(input);
+        if self.fold_accents {
+            // decompose first, so the accents are separate (combining) characters
+            output = Cow::Owned(output.nfd().filter(|c| !is_combining_mark(*c)).collect());
+        }
+        match self.form {
+            Some(UnicodeForm::Nfc) => output = Cow::Owned(output.nfc().collect()),
+            Some(UnicodeForm::Nfkc) => output = Cow::Owned(output.nfkc().collect()),
+            None => {}
+        }
+        if self.lowercase {
+            output = Cow::Owned(output.to_lowercase());
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Identity, Normalizer, StandardNormalizer, UnicodeForm};
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::assert_same;
+    use crate::trie::TrieBuilder;
+
+    #[test]
+    fn standard_normalizer_test() {
+        assert_eq!(Identity.normalize("Café"), "Café");
+        assert_eq!(StandardNormalizer::new().normalize("Café"), "Café");
+        assert_eq!(StandardNormalizer::new().lowercase().normalize("CaFé"), "café");
+        let nfc = StandardNormalizer::new().form(UnicodeForm::Nfc);
+        assert_eq!(nfc.normalize("cafe\u{301}"), nfc.normalize("caf\u{e9}"));
+        assert_eq!(nfc.normalize("\u{fb01}le"), "\u{fb01}le");
+        let nfkc = StandardNormalizer::new().form(UnicodeForm::Nfkc);
+        assert_eq!(nfkc.normalize("\u{fb01}le"), "file");
+        let folded = StandardNormalizer::new().lowercase().fold_accents();
+        assert_eq!(folded.normalize("Crème Brûlée"), "creme brulee");
+        assert_eq!(folded.normalize("cafe\u{301}"), "cafe");
+    }
+
+    #[test]
+    fn trie_vs_naive_normalized_test() {
+        let data = [
+            ("Car", 1),
+            ("carpet", 2),
+            ("Carpenter", 3),
+            ("CAFE\u{301}", 4),
+            ("café", 6),
+            ("Crème brûlée", 5),
+            ("creme fraiche", 2),
+            ("Éclair", 3),
+            ("eclipse", 4),
+            ("\u{fb01}le", 1),
+        ];
+        let prefixes = ["", "c", "CAR", "caf", "Café", "cre", "CRÈME", "e", "É", "fi", "xyz"];
+        let normalizers = [
+            StandardNormalizer::new(),
+            StandardNormalizer::new().lowercase(),
+            StandardNormalizer::new().form(UnicodeForm::Nfc),
+            StandardNormalizer::new().lowercase().form(UnicodeForm::Nfkc),
+            StandardNormalizer::new().lowercase().fold_accents(),
+        ];
+        for &normalizer in normalizers.iter() {
+            let trie = TrieBuilder::new().normalizer(normalizer).build(&data);
+            let naive = NaiveAutoComplete::with_normalizer(&data, normalizer);
+            let context = format!("normalizer: {:?}", normalizer);
+            assert_same(
+                &prefixes,
+                &[10],
+                |prefix, limit| trie.suggestions_with_limit(prefix, limit),
+                |prefix, limit| naive.suggestions_with_limit(prefix, limit),
+                &context,
+            );
+            assert_same(
+                &prefixes,
+                &[1],
+                |prefix, max_edits| trie.fuzzy_suggestions(prefix, max_edits),
+                |prefix, max_edits| naive.fuzzy_suggestions(prefix, max_edits),
+                &context,
+            );
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! The page module.
+//! This module contains the types used to page through all the suggestions of a prefix (in score order),
+//! beyond the top suggestions cached in the trie.
+
+use crate::Suggestion;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A page of suggestions, `next` is the cursor of the following page (`None` on the last page)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<P = ()> {
+    pub suggestions: Vec<Suggestion<P>>,
+    pub next: Option<Cursor>,
+}
+
+/// An opaque position in the suggestions of a prefix, i.e. the next page starts after it.
+/// A cursor can be passed around as a string (e.g. to a browser and back), see `Display` and `FromStr`.
+/// A cursor stays valid when the trie is updated: the next page starts at the next lower ranked
+/// suggestion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    /// The score and the word of the last suggestion of the previous page
+    score: u32,
+    word: Arc<String>,
+}
+
+impl Cursor {
+    pub(crate) fn new<P>(last: &Suggestion<P>) -> Self {
+        Cursor {
+            score: last.score(),
+            word: last.word.clone(),
+        }
+    }
+
+    /// Whether the `suggestion` ranks below this cursor, i.e. it belongs to the next pages
+    pub(crate) fn is_above<P>(&self, suggestion: &Suggestion<P>) -> bool {
+        let ordering = suggestion
+            .score()
+            .cmp(&self.score)
+            .then_with(|| suggestion.word.cmp(&self.word));
+        ordering == Ordering::Less
+    }
+}
+
+/// The score (8 hex digits) followed by the utf-8 bytes of the word (2 hex digits each)
+impl Display for Cursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.score)?;
+        self.word
+            .bytes()
+            .try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+/// The error returned when parsing an invalid cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCursor;
+
+impl Display for InvalidCursor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cursor")
+    }
+}
+
+impl std::error::Error for InvalidCursor {}
+
+impl FromStr for Cursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 8 || !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(InvalidCursor);
+        }
+        let score = u32::from_str_radix(&s[..8], 16).map_err(|_| InvalidCursor)?;
+        let bytes = (8..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| InvalidCursor)?;
+        let word = String::from_utf8(bytes).map_err(|_| InvalidCursor)?;
+        Ok(Cursor {
+            score,
+            word: Arc::new(word),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cursor, InvalidCursor, Page};
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::TrieBuilder;
+    use crate::Suggestion;
+    use std::sync::Arc;
+
+    #[test]
+    fn cursor_test() {
+        for (word, score) in [("carpet", 2), ("", 0), ("日本 語", u32::MAX)].iter() {
+            let cursor = Cursor::new(&Suggestion::new(Arc::new(word.to_string()), *score));
+            assert_eq!(cursor.to_string().parse::<Cursor>(), Ok(cursor));
+        }
+        assert_eq!("0000000263".parse::<Cursor>().unwrap().word.as_str(), "c");
+        assert_eq!("".parse::<Cursor>(), Err(InvalidCursor));
+        assert_eq!("000000026".parse::<Cursor>(), Err(InvalidCursor));
+        assert_eq!("0000000x63".parse::<Cursor>(), Err(InvalidCursor));
+        assert_eq!("00000002ff".parse::<Cursor>(), Err(InvalidCursor));
+        assert_eq!("0000000é".parse::<Cursor>(), Err(InvalidCursor));
+        assert_eq!("00000002+f".parse::<Cursor>(), Err(InvalidCursor));
+    }
+
+    /// Returns all the pages, every page after the cursor of the previous one
+    fn pages(page: impl Fn(Option<&Cursor>) -> Page) -> Vec<Page> {
+        let mut pages = vec![page(None)];
+        while let Some(next) = pages.last().and_then(|last| last.next.clone()) {
+            pages.push(page(Some(&next)));
+        }
+        pages
+    }
+
+    #[test]
+    fn trie_vs_naive_page_test() {
+        let mut rng = Rng(0x9a9e_5eed_0001_0002);
+        let corpus = multi_script_corpus(&mut rng, 400);
+        let data = as_data(&corpus);
+        let prefixes = prefixes(&data[..20], 2);
+        let naive = NaiveAutoComplete::new(&data);
+        for max_suggestions in [1, 3, 5].iter() {
+            let trie = TrieBuilder::new().max_suggestions(*max_suggestions).build(&data);
+            let context = format!("max_suggestions: {}", max_suggestions);
+            assert_same(
+                &prefixes,
+                &[2, 7],
+                |prefix, size| pages(|cursor| trie.suggestions_page(prefix, cursor, size)),
+                |prefix, size| pages(|cursor| naive.suggestions_page(prefix, cursor, size)),
+                &context,
+            );
+            // the pages hold all the suggestions, in order
+            assert_same(
+                &prefixes,
+                &[2, 7],
+                |prefix, size| {
+                    let pages = pages(|cursor| trie.suggestions_page(prefix, cursor, size));
+                    pages.into_iter().flat_map(|page| page.suggestions).collect::<Vec<_>>()
+                },
+                |prefix, _| naive.suggestions_with_limit(prefix, usize::MAX),
+                &context,
+            );
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! The personal module.
+//! A `Personalizer` keeps a small history per user (the words the user picked, with their counts)
+//! on top of a shared global `Trie`, which it never changes. The suggestions of a user (see
+//! `Personal`) blend the words of the user's history, boosted by their counts, with the global
+//! words at query time. A history holds at most `capacity` words, the least recently used one is
+//! dropped first.
+
+use crate::trie::Trie;
+use crate::{AutoCompletor, Suggestion};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The number of words of a user's history, unless configured otherwise
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+/// The per user histories on top of a shared global `Trie`
+#[derive(Debug)]
+pub struct Personalizer {
+    global: Arc<Trie>,
+    users: HashMap<String, History>,
+    /// Added to the score of a word for every use in a user's history
+    boost: u32,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct History {
+    /// The used words, scored by their counts
+    trie: Trie,
+    /// The last use of every word (by its key), the larger the more recent
+    uses: HashMap<Vec<char>, u64>,
+    /// The keys of the words by their last use, the least recently used first
+    recent: BTreeMap<u64, Vec<char>>,
+    clock: u64,
+}
+
+impl Personalizer {
+    /// Initializes the personalizer (without any history) on top of the `global` words.
+    /// The histories use the configuration and the normalizer of the global trie.
+    pub fn new(global: Arc<Trie>) -> Self {
+        Personalizer {
+            global,
+            users: HashMap::new(),
+            boost: 1,
+            capacity: DEFAULT_HISTORY_CAPACITY,
+        }
+    }
+
+    /// Sets the boost of a word for every use in a user's history (1 by default)
+    pub fn boost(mut self, boost: u32) -> Self {
+        self.boost = boost;
+        self
+    }
+
+    /// Sets the number of words of a user's history (at least 1)
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// The shared global words, the personalizer never changes them
+    pub fn global(&self) -> &Arc<Trie> {
+        &self.global
+    }
+
+    /// Returns the names of the users with a history (in no particular order)
+    pub fn users(&self) -> impl Iterator<Item = &str> {
+        self.users.keys().map(|user| &user[..])
+    }
+
+    /// Adds a use of the given `word` to the `user`'s history, e.g. when the user picks it from
+    /// the suggestions, and drops the least recently used word if the history is full.
+    /// Returns the number of uses of the word in the history.
+    pub fn add_history(&mut self, user: &str, word: &str) -> u32 {
+        let global = &self.global;
+        let history = self.users.entry(user.to_string()).or_insert_with(|| History {
+            trie: global.empty_like(),
+            uses: HashMap::new(),
+            recent: BTreeMap::new(),
+            clock: 0,
+        });
+        let key = global.key(word);
+        let count = history.trie.get(word).map_or(0, |s| s.score()).saturating_add(1);
+        // a history word that is also a global word shares its string with the global trie
+        let shared = global
+            .get(word)
+            .map(|suggestion| suggestion.word)
+            .filter(|shared| **shared == word)
+            .unwrap_or_else(|| Arc::new(word.to_string()));
+        history.trie.upsert_shared(shared, count);
+        history.clock += 1;
+        if let Some(used) = history.uses.insert(key.clone(), history.clock) {
+            history.recent.remove(&used);
+        }
+        history.recent.insert(history.clock, key);
+        if history.uses.len() > self.capacity {
+            if let Some((_, oldest)) = history.recent.pop_first() {
+                history.uses.remove(&oldest);
+                history.trie.remove_key(&oldest);
+            }
+        }
+        count
+    }
+
+    /// Clears the `user`'s history, returns `false` if the user has no history
+    pub fn clear_history(&mut self, user: &str) -> bool {
+        self.users.remove(user).is_some()
+    }
+
+    /// Returns the words of the `user`'s history with their counts, the most recently used first
+    pub fn history(&self, user: &str) -> Vec<(String, u32)> {
+        let history = match self.users.get(user) {
+            Some(history) => history,
+            None => return vec![],
+        };
+        history
+            .recent
+            .values()
+            .rev()
+            .filter_map(|key| history.trie.get_key(key))
+            .map(|suggestion| ((*suggestion.word).clone(), suggestion.score()))
+            .collect()
+    }
+
+    /// Returns the view of the `user`, a user without a history only gets the global suggestions
+    pub fn personal<'a>(&'a self, user: &str) -> Personal<'a> {
+        Personal {
+            global: &self.global,
+            history: self.users.get(user),
+            boost: self.boost,
+        }
+    }
+}
+
+/// The words of a user's history blended with the global words, see `Personalizer::personal`
+#[derive(Debug, Clone, Copy)]
+pub struct Personal<'a> {
+    global: &'a Trie,
+    history: Option<&'a History>,
+    boost: u32,
+}
+
+impl Personal<'_> {
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, self.global.config().max_suggestions)
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`.
+    /// The score of a word of the user's history is its global score (0 if it is not a global
+    /// word) plus the boost for every use of it.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        let history = match self.history {
+            Some(history) => history,
+            None => return self.global.suggestions_with_limit(prefix, limit),
+        };
+        // the history is small, so all of its words for the prefix are blended
+        let personal: HashMap<Vec<char>, Suggestion> = history
+            .trie
+            .iter_prefix(prefix)
+            .filter_map(|(word, count)| history.trie.get(word).map(|s| (s, count)))
+            .map(|(suggestion, count)| {
+                let global = self.global.get(&suggestion.word).map_or(0, |s| s.score());
+                let boost = self.boost.saturating_mul(count);
+                let key = self.global.key(&suggestion.word);
+                (key, suggestion.with_score(global.saturating_add(boost)))
+            })
+            .collect();
+        // the other words keep their global scores, the top `limit` of them are among the
+        // global top `limit + personal.len()`
+        let global: Vec<Suggestion> = self
+            .global
+            .suggestions_with_limit(prefix, limit.saturating_add(personal.len()))
+            .into_iter()
+            .filter(|suggestion| !personal.contains_key(&self.global.key(&suggestion.word)))
+            .collect();
+        let mut suggestions: Vec<Suggestion> = personal.into_values().chain(global).collect();
+        suggestions.sort_by(|a, b| b.cmp(a));
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+impl AutoCompletor for Personal<'_> {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions(prefix)
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Personalizer;
+    use crate::normalize::StandardNormalizer;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::{Trie, TrieBuilder};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn words(personalizer: &Personalizer, user: &str, prefix: &str) -> Vec<(String, u32)> {
+        personalizer
+            .personal(user)
+            .suggestions(prefix)
+            .iter()
+            .map(|s| ((*s.word).clone(), s.score()))
+            .collect()
+    }
+
+    fn owned(words: &[(&str, u32)]) -> Vec<(String, u32)> {
+        words.iter().map(|(w, s)| (w.to_string(), *s)).collect()
+    }
+
+    #[test]
+    fn personalizer_test() {
+        let global = Arc::new(Trie::new(&[
+            ("rust", 50),
+            ("ruby", 40),
+            ("russia", 30),
+            ("rugby", 20),
+            ("python", 60),
+        ]));
+        let mut personalizer = Personalizer::new(global.clone()).boost(16);
+        assert_eq!(personalizer.add_history("alice", "rugby"), 1);
+        assert_eq!(personalizer.add_history("alice", "rugby"), 2);
+        assert_eq!(personalizer.add_history("alice", "rumba"), 1);
+        assert_eq!(personalizer.add_history("bob", "ruby"), 1);
+        assert_eq!(
+            words(&personalizer, "alice", "ru"),
+            owned(&[("rugby", 52), ("rust", 50), ("ruby", 40), ("russia", 30), ("rumba", 16)])
+        );
+        assert_eq!(words(&personalizer, "bob", "ru")[0], ("ruby".to_string(), 56));
+        // the history words are only suggested for their user
+        assert!(words(&personalizer, "bob", "rum").is_empty());
+        assert_eq!(personalizer.personal("carol").suggestions("ru"), global.suggestions("ru"));
+        assert_eq!(words(&personalizer, "alice", "p"), owned(&[("python", 60)]));
+        // the global words are shared, and never changed
+        let rugby = personalizer.personal("alice").suggestions("rug")[0].word.clone();
+        assert!(Arc::ptr_eq(&rugby, &global.get("rugby").unwrap().word));
+        assert_eq!(global.get("rugby").map(|s| s.score()), Some(20));
+        assert_eq!(
+            personalizer.history("alice"),
+            owned(&[("rumba", 1), ("rugby", 2)])
+        );
+        assert!(personalizer.clear_history("alice"));
+        assert!(!personalizer.clear_history("alice"));
+        assert_eq!(personalizer.history("alice"), vec![]);
+        assert_eq!(words(&personalizer, "alice", "rug"), owned(&[("rugby", 20)]));
+        assert_eq!(personalizer.users().collect::<Vec<&str>>(), vec!["bob"]);
+    }
+
+    #[test]
+    fn personalizer_capacity_test() {
+        let global = TrieBuilder::new()
+            .normalizer(StandardNormalizer::new().lowercase())
+            .build(&[("Paris", 3), ("Panama", 2)]);
+        let mut personalizer = Personalizer::new(Arc::new(global)).boost(10).capacity(2);
+        personalizer.add_history("alice", "paris");
+        personalizer.add_history("alice", "pasta");
+        personalizer.add_history("alice", "PARIS");
+        // the least recently used word ("pasta") is dropped
+        personalizer.add_history("alice", "panama");
+        assert_eq!(personalizer.history("alice"), owned(&[("panama", 1), ("PARIS", 2)]));
+        assert_eq!(
+            words(&personalizer, "alice", "pa"),
+            owned(&[("PARIS", 23), ("panama", 12)])
+        );
+        assert_eq!(personalizer.personal("alice").suggestions_with_limit("PA", 1).len(), 1);
+    }
+
+    #[test]
+    fn personalizer_vs_naive_test() {
+        let mut rng = Rng(0x5eed_f00d_0b5e_55ed);
+        let corpus = multi_script_corpus(&mut rng, 300);
+        let data = as_data(&corpus);
+        let global = Arc::new(Trie::new(&data));
+        let mut personalizer = Personalizer::new(global.clone()).boost(7).capacity(20);
+        let mut blended: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        for user in ["alice", "bob", "carol"].iter() {
+            // some global words and some words of the user only, used a few times
+            let extra = multi_script_corpus(&mut rng, 10);
+            let common: Vec<(String, u32)> =
+                corpus.iter().filter(|_| rng.next(25) == 0).cloned().collect();
+            for (word, _) in common.into_iter().chain(extra) {
+                for _ in 0..1 + rng.next(3) {
+                    personalizer.add_history(user, &word);
+                }
+            }
+            let words = blended.entry(user.to_string()).or_default();
+            words.extend(corpus.iter().cloned());
+            for (word, count) in personalizer.history(user) {
+                let score = global.get(&word).map_or(0, |s| s.score());
+                words.insert(word, score + 7 * count);
+            }
+        }
+        for (user, words) in blended.iter() {
+            let data: Vec<(&str, u32)> = words.iter().map(|(w, s)| (&w[..], *s)).collect();
+            let naive = NaiveAutoComplete::new(&data);
+            let personal = personalizer.personal(user);
+            assert_same(
+                &prefixes(&data, 3),
+                &[1, 5, 12],
+                |prefix, limit| personal.suggestions_with_limit(prefix, limit),
+                |prefix, limit| naive.suggestions_with_limit(prefix, limit),
+                &format!("user: {}", user),
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! The phrase module.
+//! A `PhraseIndex` completes multi-word phrases token by token: the earlier tokens of the prefix
+//! match whole tokens of a phrase and the last one is completed, e.g. "new yo" finds
+//! "New York pizza" (and "best new york bagels"), but "ne yo" does not.
+//! The phrases are indexed in a `Trie` from every token on, keyed by the normalized tokens joined
+//! with a single space, so the spacing and the punctuation of the prefix do not matter.
+
+use crate::infix::SEPARATOR;
+use crate::normalize::Normalizer;
+use crate::trie::{Trie, TrieBuilder};
+use crate::{AutoCompletor, Suggestion};
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::Arc;
+use unicode_normalization::char::is_combining_mark;
+
+/// Splits a (normalized) text into its tokens
+pub trait Tokenizer: Debug + Send + Sync {
+    /// Returns the (byte) ranges of the tokens of the `text`, in order
+    fn tokenize(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+/// The default tokenizer, a token is a run of letters, digits and combining marks,
+/// e.g. "New-York  pizza!" has the tokens "New", "York" and "pizza".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WordTokenizer;
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Range<usize>> {
+        let mut tokens = vec![];
+        let mut start = None;
+        for (i, ch) in text.char_indices() {
+            let in_token = ch.is_alphanumeric() || is_combining_mark(ch);
+            match start {
+                None if in_token => start = Some(i),
+                Some(s) if !in_token => {
+                    tokens.push(s..i);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            tokens.push(s..text.len());
+        }
+        tokens
+    }
+}
+
+/// A phrase that matched the prefix from its token at `position` (0 for the first token).
+/// The position is the first one from which the prefix matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhraseSuggestion {
+    pub suggestion: Suggestion,
+    pub position: usize,
+}
+
+/// Completes the last token of a multi-word prefix, see `Tokenizer`
+#[derive(Debug)]
+pub struct PhraseIndex {
+    index: Trie,
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl PhraseIndex {
+    /// Initializes the index from a given list of phrases and scores, split by a `WordTokenizer`
+    pub fn new(input: &[(&str, u32)]) -> Self {
+        PhraseIndex::with_builder(TrieBuilder::new(), input, WordTokenizer)
+    }
+
+    /// Initializes the index with the configuration and the normalizer of the `builder`,
+    /// the phrases are split by the `tokenizer`
+    pub fn with_builder(
+        builder: TrieBuilder,
+        input: &[(&str, u32)],
+        tokenizer: impl Tokenizer + 'static,
+    ) -> Self {
+        let mut phrases = PhraseIndex {
+            index: builder.build_empty(),
+            tokenizer: Arc::new(tokenizer),
+        };
+        input.iter().for_each(|(phrase, score)| {
+            phrases.upsert(phrase, *score);
+        });
+        phrases
+    }
+
+    fn tokens(&self, text: &str) -> (Vec<Vec<char>>, bool) {
+        tokens(self.index.normalizer(), self.tokenizer.as_ref(), text)
+    }
+
+    /// Returns the keys of the `phrase` in the index, the tokens from every position on
+    /// (the first one is the whole phrase)
+    fn keys(&self, phrase: &str) -> Vec<Vec<char>> {
+        let (tokens, _) = self.tokens(phrase);
+        let whole = join(&tokens);
+        (0..tokens.len().max(1))
+            .map(|position| {
+                let mut key = join(&tokens[position.min(tokens.len())..]);
+                key.push(SEPARATOR);
+                key.extend_from_slice(&whole);
+                key
+            })
+            .collect()
+    }
+
+    /// Inserts the given `phrase` with the `score`, or replaces it if it is already present.
+    /// The phrases with the same (normalized) tokens are the same, e.g. "New York" and "New-York".
+    /// Returns the previous suggestion (if any).
+    pub fn upsert(&mut self, phrase: &str, score: u32) -> Option<Suggestion> {
+        let suggestion = Suggestion::new(Arc::new(phrase.to_string()), score);
+        let mut previous = None;
+        for key in self.keys(phrase) {
+            let replaced = self.index.upsert_at(&key, suggestion.clone());
+            previous = previous.or(replaced);
+        }
+        previous
+    }
+
+    /// Removes the given `phrase` and returns its suggestion (if it was present).
+    pub fn remove(&mut self, phrase: &str) -> Option<Suggestion> {
+        let mut removed = None;
+        for key in self.keys(phrase) {
+            let suggestion = self.index.remove_key(&key);
+            removed = removed.or(suggestion);
+        }
+        removed
+    }
+
+    pub fn suggestions(&self, prefix: &str) -> Vec<PhraseSuggestion> {
+        self.suggestions_with_limit(prefix, self.index.config().max_suggestions)
+    }
+
+    /// Returns the top `limit` phrases that match the tokens of the given `prefix`:
+    /// its earlier tokens are matched exactly and the last one as a prefix, unless it is complete
+    /// (e.g. "new york "), then the phrase must go on after it.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<PhraseSuggestion> {
+        let (tokens, complete) = self.tokens(prefix);
+        let mut query = join(&tokens);
+        if complete {
+            query.push(' ');
+        }
+        self.index
+            .top_suggestions(&query, limit)
+            .into_iter()
+            .map(|suggestion| {
+                let (tokens, _) = self.tokens(&suggestion.word);
+                let position = (0..tokens.len())
+                    .find(|&position| join(&tokens[position..]).starts_with(&query))
+                    .unwrap_or(0);
+                PhraseSuggestion {
+                    suggestion,
+                    position,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the normalized tokens of the `text`, and whether its last token is complete,
+/// i.e. followed by a separator (e.g. "new york ").
+pub(crate) fn tokens(
+    normalizer: &dyn Normalizer,
+    tokenizer: &dyn Tokenizer,
+    text: &str,
+) -> (Vec<Vec<char>>, bool) {
+    let normalized = normalizer.normalize(text);
+    let ranges = tokenizer.tokenize(&normalized);
+    let complete = ranges.last().is_some_and(|last| last.end < normalized.len());
+    let tokens = ranges
+        .into_iter()
+        .map(|range| normalized[range].chars().collect())
+        .collect();
+    (tokens, complete)
+}
+
+/// Joins the tokens with a single space
+pub(crate) fn join(tokens: &[Vec<char>]) -> Vec<char> {
+    let mut joined = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            joined.push(' ');
+        }
+        joined.extend_from_slice(token);
+    }
+    joined
+}
+
+impl AutoCompletor for PhraseIndex {
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, self.index.config().max_suggestions)
+            .into_iter()
+            .map(|phrase| phrase.suggestion)
+            .collect()
+    }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+            .into_iter()
+            .map(|phrase| phrase.suggestion)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhraseIndex, Tokenizer, WordTokenizer};
+    use crate::normalize::StandardNormalizer;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, Rng};
+    use crate::trie::TrieBuilder;
+    use std::collections::HashSet;
+
+    fn matches(phrases: &PhraseIndex, prefix: &str) -> Vec<(String, usize)> {
+        phrases
+            .suggestions(prefix)
+            .iter()
+            .map(|s| ((*s.suggestion.word).clone(), s.position))
+            .collect()
+    }
+
+    #[test]
+    fn word_tokenizer_test() {
+        let tokens = |text: &str| -> Vec<String> {
+            let ranges = WordTokenizer.tokenize(text);
+            ranges.into_iter().map(|range| text[range].to_string()).collect()
+        };
+        assert_eq!(tokens("New-York  pizza!"), vec!["New", "York", "pizza"]);
+        assert_eq!(tokens(" café 42 "), vec!["café", "42"]);
+        assert_eq!(tokens("cafe\u{301}"), vec!["cafe\u{301}"]);
+        assert_eq!(tokens("日本 語"), vec!["日本", "語"]);
+        assert!(tokens(" - ").is_empty());
+    }
+
+    #[test]
+    fn phrase_suggestions_test() {
+        let builder = TrieBuilder::new().normalizer(StandardNormalizer::new().lowercase());
+        let input = [
+            ("New York pizza", 5),
+            ("new york", 4),
+            ("best new york bagels", 3),
+            ("newark airport", 2),
+            ("New  Yorker magazine", 1),
+        ];
+        let mut phrases = PhraseIndex::with_builder(builder, &input, WordTokenizer);
+        assert_eq!(
+            matches(&phrases, "new yo"),
+            vec![
+                ("New York pizza".to_string(), 0),
+                ("new york".to_string(), 0),
+                ("best new york bagels".to_string(), 1),
+                ("New  Yorker magazine".to_string(), 0),
+            ]
+        );
+        // the earlier tokens match whole tokens only
+        assert!(matches(&phrases, "ne yo").is_empty());
+        assert_eq!(matches(&phrases, "new")[3], ("newark airport".to_string(), 0));
+        // a complete last token must be followed by more tokens
+        assert_eq!(
+            matches(&phrases, "NEW-YORK "),
+            vec![("New York pizza".to_string(), 0), ("best new york bagels".to_string(), 1)]
+        );
+        assert_eq!(matches(&phrases, "york b"), vec![("best new york bagels".to_string(), 2)]);
+        assert_eq!(phrases.suggestions("").len(), 5);
+        assert_eq!(phrases.upsert("new york", 9).map(|s| s.score()), Some(4));
+        assert_eq!(matches(&phrases, "york")[0], ("new york".to_string(), 1));
+        assert_eq!(phrases.remove("New York pizza").map(|s| s.score()), Some(5));
+        assert_eq!(matches(&phrases, "pi"), vec![]);
+        assert_eq!(PhraseIndex::new(&[("New York", 1)]).suggestions("new").len(), 0);
+    }
+
+    #[test]
+    fn phrase_index_vs_naive_test() {
+        let mut rng = Rng(0x9e77_ab1e_0042_1701);
+        let words = multi_script_corpus(&mut rng, 60);
+        let separators = [" ", "  ", "-", ", "];
+        let mut seen = HashSet::new();
+        let corpus: Vec<(String, u32)> = (0..200)
+            .map(|_| {
+                let mut phrase = words[rng.next(words.len())].0.clone();
+                for _ in 0..rng.next(4) {
+                    phrase.push_str(separators[rng.next(separators.len())]);
+                    phrase.push_str(&words[rng.next(words.len())].0);
+                }
+                (phrase, rng.next(20) as u32)
+            })
+            // the phrases with the same tokens are the same phrase in the index
+            .filter(|(phrase, _)| {
+                let tokens = WordTokenizer.tokenize(phrase);
+                seen.insert(tokens.into_iter().map(|r| &phrase[r]).collect::<Vec<_>>().join(" "))
+            })
+            .collect();
+        let data = as_data(&corpus);
+        let builder = TrieBuilder::new().max_suggestions(3);
+        let phrases = PhraseIndex::with_builder(builder, &data, WordTokenizer);
+        let naive = NaiveAutoComplete::new(&data);
+        // the prefixes of the phrases and the prefixes starting at their second token
+        let mut prefixes = vec![];
+        for (phrase, _) in corpus.iter() {
+            for (end, _) in phrase.char_indices().skip(1).chain(Some((phrase.len(), ' '))) {
+                for start in [0, phrase.find(' ').map_or(0, |i| i + 1)].iter() {
+                    prefixes.push(&phrase[*start.min(&end)..end]);
+                }
+            }
+        }
+        assert_same(
+            &prefixes,
+            &[1, 5],
+            |prefix, limit| phrases.suggestions_with_limit(prefix, limit),
+            |prefix, limit| naive.phrase_suggestions(prefix, limit, &WordTokenizer),
+            "",
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! The rank module.
+//! A `Ranker` orders the suggestions of a prefix at query time, e.g. combining the static score with
+//! an exact match boost and the recency of a word. The trie over-fetches candidates (by static score)
+//! and re-ranks them with the ranker, see `Trie::ranked_suggestions`.
+
+use crate::Suggestion;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// The query time signals of a candidate suggestion
+#[derive(Debug, Clone, Copy)]
+pub struct Signals<'a> {
+    pub word: &'a str,
+    /// The static score
+    pub score: u32,
+    /// The number of characters of the (normalized) prefix
+    pub prefix_len: usize,
+    /// The number of characters of the (normalized) word
+    pub word_len: usize,
+    /// The (normalized) word is the prefix itself
+    pub exact: bool,
+}
+
+impl<'a> Signals<'a> {
+    pub(crate) fn new<P>(
+        suggestion: &'a Suggestion<P>,
+        prefix_len: usize,
+        word_len: usize,
+        exact: bool,
+    ) -> Self {
+        Signals {
+            word: &suggestion.word,
+            score: suggestion.score(),
+            prefix_len,
+            word_len,
+            exact,
+        }
+    }
+}
+
+/// Ranks the candidate suggestions of a prefix, higher ranks first
+pub trait Ranker: Debug + Send + Sync {
+    fn rank(&self, signals: &Signals) -> f64;
+
+    /// Orders the candidates with the same rank, the greater one first.
+    /// By default the greater word comes first (like `Ord for Suggestion`).
+    fn tie_break(&self, a: &Signals, b: &Signals) -> Ordering {
+        a.word.cmp(b.word)
+    }
+
+    /// The number of candidates (by static score) to re-rank for the top `limit` suggestions
+    fn candidates(&self, limit: usize) -> usize {
+        limit.saturating_mul(4)
+    }
+}
+
+/// Ranks by the static score only, i.e. the order of `Trie::suggestions`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StaticRanker;
+
+impl Ranker for StaticRanker {
+    fn rank(&self, signals: &Signals) -> f64 {
+        signals.score as f64
+    }
+
+    fn candidates(&self, limit: usize) -> usize {
+        limit
+    }
+}
+
+/// Ranks by a weighted sum of the signals:
+/// `score * score_weight + exact_match_boost (if exact) + recency_boost * decay
+///  - length_penalty * (word_len - prefix_len)`
+/// where the decay halves every `half_life` since the word was last `touch`ed.
+#[derive(Debug)]
+pub struct WeightedRanker {
+    score_weight: f64,
+    exact_match_boost: f64,
+    length_penalty: f64,
+    recency_boost: f64,
+    half_life: Duration,
+    /// The last time each word was used (e.g. selected)
+    recent: RwLock<HashMap<String, Instant>>,
+}
+
+impl Default for WeightedRanker {
+    fn default() -> Self {
+        WeightedRanker {
+            score_weight: 1.0,
+            exact_match_boost: 0.0,
+            length_penalty: 0.0,
+            recency_boost: 0.0,
+            half_life: Duration::from_secs(24 * 60 * 60),
+            recent: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl WeightedRanker {
+    pub fn new() -> Self {
+        WeightedRanker::default()
+    }
+
+    pub fn score_weight(mut self, weight: f64) -> Self {
+        self.score_weight = weight;
+        self
+    }
+
+    /// Added when the prefix is the whole word
+    pub fn exact_match_boost(mut self, boost: f64) -> Self {
+        self.exact_match_boost = boost;
+        self
+    }
+
+    /// Subtracted for every character still to be typed, i.e. favors the shorter completions
+    pub fn length_penalty(mut self, penalty: f64) -> Self {
+        self.length_penalty = penalty;
+        self
+    }
+
+    /// Added (decayed by the `half_life`) for the words that were `touch`ed
+    pub fn recency_boost(mut self, boost: f64, half_life: Duration) -> Self {
+        self.recency_boost = boost;
+        self.half_life = half_life;
+        self
+    }
+
+    /// Records that the `word` was used now
+    pub fn touch(&self, word: &str) {
+        self.touch_at(word, Instant::now());
+    }
+
+    /// Records that the `word` was used at the given instant
+    pub fn touch_at(&self, word: &str, at: Instant) {
+        let mut recent = self.recent.write().unwrap();
+        recent.insert(word.to_string(), at);
+    }
+
+    fn recency(&self, word: &str) -> f64 {
+        let recent = self.recent.read().unwrap();
+        match recent.get(word) {
+            Some(at) if !self.half_life.is_zero() => {
+                let half_lives = at.elapsed().as_secs_f64() / self.half_life.as_secs_f64();
+                0.5f64.powf(half_lives)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl Ranker for WeightedRanker {
+    fn rank(&self, signals: &Signals) -> f64 {
+        let mut rank = signals.score as f64 * self.score_weight;
+        if signals.exact {
+            rank += self.exact_match_boost;
+        }
+        if self.recency_boost != 0.0 {
+            rank += self.recency_boost * self.recency(signals.word);
+        }
+        let remaining = signals.word_len.saturating_sub(signals.prefix_len);
+        rank - self.length_penalty * remaining as f64
+    }
+}
+
+/// Orders the candidates by the `ranker` (best first), keeping the top `limit` of them.
+pub(crate) fn rank<P>(
+    ranker: &dyn Ranker,
+    candidates: Vec<(&Suggestion<P>, Signals)>,
+    limit: usize,
+) -> Vec<Suggestion<P>> {
+    let mut ranked: Vec<(f64, &Suggestion<P>, Signals)> = candidates
+        .into_iter()
+        .map(|(suggestion, signals)| (ranker.rank(&signals), suggestion, signals))
+        .collect();
+    ranked.sort_by(|(rank_a, _, a), (rank_b, _, b)| {
+        rank_b
+            .partial_cmp(rank_a)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| ranker.tie_break(b, a))
+    });
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(_, suggestion, _)| suggestion.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rank, Ranker, Signals, StaticRanker, WeightedRanker};
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::trie::TrieBuilder;
+    use crate::Suggestion;
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn suggestion(word: &str, score: u32) -> Suggestion {
+        Suggestion::new(Arc::new(word.to_string()), score)
+    }
+
+    fn signals<'a>(suggestion: &'a Suggestion, prefix: &str) -> Signals<'a> {
+        let prefix_len = prefix.chars().count();
+        let word_len = suggestion.word.chars().count();
+        Signals::new(suggestion, prefix_len, word_len, *suggestion.word == prefix)
+    }
+
+    fn words(ranker: &dyn Ranker, suggestions: &[Suggestion], prefix: &str) -> Vec<String> {
+        let candidates = suggestions.iter().map(|s| (s, signals(s, prefix))).collect();
+        rank(ranker, candidates, 3)
+            .iter()
+            .map(|s| (*s.word).clone())
+            .collect()
+    }
+
+    #[test]
+    fn weighted_ranker_test() {
+        let suggestions = [
+            suggestion("car", 1),
+            suggestion("carpet", 4),
+            suggestion("carpenter", 5),
+            suggestion("cart", 4),
+        ];
+        assert_eq!(
+            words(&StaticRanker, &suggestions, "car"),
+            vec!["carpenter", "cart", "carpet"]
+        );
+        let exact = WeightedRanker::new().exact_match_boost(10.0);
+        assert_eq!(words(&exact, &suggestions, "car"), vec!["car", "carpenter", "cart"]);
+        let short = WeightedRanker::new().length_penalty(1.0);
+        assert_eq!(words(&short, &suggestions, "car"), vec!["cart", "carpet", "car"]);
+        let recent = WeightedRanker::new().recency_boost(8.0, Duration::from_secs(60));
+        let base = Instant::now();
+        recent.touch_at("carpet", base);
+        // ten half lives earlier, the clock may not go back that far (e.g. right after a boot)
+        if let Some(before) = base.checked_sub(Duration::from_secs(600)) {
+            recent.touch_at("cart", before);
+        }
+        assert_eq!(words(&recent, &suggestions, "car"), vec!["carpet", "carpenter", "cart"]);
+    }
+
+    #[test]
+    fn tie_break_test() {
+        #[derive(Debug)]
+        struct ShorterFirst;
+        impl Ranker for ShorterFirst {
+            fn rank(&self, signals: &Signals) -> f64 {
+                signals.score as f64
+            }
+
+            fn tie_break(&self, a: &Signals, b: &Signals) -> Ordering {
+                b.word_len.cmp(&a.word_len)
+            }
+        }
+        let suggestions = [suggestion("carpet", 4), suggestion("cart", 4), suggestion("ca", 4)];
+        assert_eq!(words(&StaticRanker, &suggestions, "ca"), vec!["cart", "carpet", "ca"]);
+        assert_eq!(words(&ShorterFirst, &suggestions, "ca"), vec!["ca", "cart", "carpet"]);
+    }
+
+    #[test]
+    fn trie_vs_naive_ranked_test() {
+        let mut rng = Rng(0x7a7e_d00d_1111_2222);
+        let corpus = multi_script_corpus(&mut rng, 400);
+        let data = as_data(&corpus);
+        let trie = TrieBuilder::new().max_suggestions(3).build(&data);
+        let naive = NaiveAutoComplete::new(&data);
+        let prefixes = prefixes(&data, usize::MAX);
+        // the re-ranked candidates always contain the best suggestions for these rankers
+        let rankers: Vec<Box<dyn Ranker>> = vec![
+            Box::new(StaticRanker),
+            Box::new(WeightedRanker::new().exact_match_boost(100.0)),
+        ];
+        for (index, ranker) in rankers.iter().enumerate() {
+            assert_same(
+                &prefixes,
+                &[1, 5],
+                |prefix, limit| trie.ranked_suggestions(prefix, limit, ranker.as_ref()),
+                |prefix, limit| naive.ranked_suggestions(prefix, limit, ranker.as_ref()),
+                &format!("ranker: {}", index),
+            );
+        }
+    }
+}
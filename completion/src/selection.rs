@@ -0,0 +1,103 @@
+//! The selection module.
+//! A suggestion picked by a user (see `Trie::record_selection`) boosts the score of its word, and
+//! its score for the prefix it was picked for. The boosts decay exponentially (halving every
+//! `TrieConfig::selection_half_life`), so the stale trends fade. The boost of a word is part of
+//! its cached score (so it ranks higher for all its prefixes) and it only decays, so the cached score
+//! is an upper bound of the current one which is evaluated at query time.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// A decaying boost, `value` at the instant `at`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Boost {
+    value: f64,
+    at: Instant,
+}
+
+impl Boost {
+    pub(crate) fn new(at: Instant) -> Self {
+        Boost { value: 0.0, at }
+    }
+
+    /// Returns the (decayed) value at the instant `now`, a zero half life decays at once
+    pub(crate) fn value_at(&self, half_life: Duration, now: Instant) -> f64 {
+        if half_life.is_zero() {
+            return 0.0;
+        }
+        let age = now.saturating_duration_since(self.at);
+        let half_lives = age.as_secs_f64() / half_life.as_secs_f64();
+        self.value * 0.5f64.powf(half_lives)
+    }
+
+    /// Returns the (decayed) value at the instant `now`, rounded to a score
+    pub(crate) fn score_at(&self, half_life: Duration, now: Instant) -> u32 {
+        self.value_at(half_life, now).round() as u32
+    }
+
+    /// Adds the `amount` to the value (decayed) at the instant `now`
+    pub(crate) fn add(&mut self, amount: u32, half_life: Duration, now: Instant) {
+        let value = if now > self.at { self.value_at(half_life, now) } else { self.value };
+        self.value = value + amount as f64;
+        self.at = self.at.max(now);
+    }
+}
+
+/// The boosts of the selected words, by their (normalized) keys
+#[derive(Debug, Default)]
+pub(crate) struct Selections {
+    /// The boost of a word and the part of it in the (cached) score of the word in the trie,
+    /// i.e. its value when the score was last updated
+    pub(crate) words: BTreeMap<Vec<char>, (Boost, u32)>,
+    /// The boosts of the words selected for a prefix
+    pub(crate) prefixes: HashMap<Vec<char>, HashMap<Vec<char>, Boost>>,
+}
+
+impl Selections {
+    /// Returns the boosts of the selected words that start with the given `prefix` key
+    pub(crate) fn words_with_prefix<'a>(
+        &'a self,
+        prefix: &'a [char],
+    ) -> impl Iterator<Item = (&'a Vec<char>, &'a (Boost, u32))> + 'a {
+        self.words
+            .range(prefix.to_vec()..)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    /// Forgets the boosts of the word with the given `key`
+    pub(crate) fn remove(&mut self, key: &[char]) {
+        self.words.remove(key);
+        for end in 0..=key.len() {
+            if let Some(boosts) = self.prefixes.get_mut(&key[..end]) {
+                boosts.remove(key);
+                if boosts.is_empty() {
+                    self.prefixes.remove(&key[..end]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Boost;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn boost_test() {
+        let half_life = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut boost = Boost::new(start);
+        boost.add(8, half_life, start);
+        assert_eq!(boost.score_at(half_life, start), 8);
+        assert_eq!(boost.score_at(half_life, start + half_life), 4);
+        assert_eq!(boost.score_at(half_life, start + half_life * 10), 0);
+        // the older value is decayed before the amount is added
+        boost.add(8, half_life, start + half_life * 2);
+        assert_eq!(boost.score_at(half_life, start + half_life * 2), 10);
+        // an earlier instant does not decay (nor grow) the value
+        boost.add(1, half_life, start);
+        assert_eq!(boost.score_at(half_life, start + half_life * 2), 11);
+        assert_eq!(boost.score_at(Duration::from_secs(0), start), 0);
+    }
+}
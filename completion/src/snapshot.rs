@@ -0,0 +1,408 @@
+//! The snapshot module. This module defines the binary format used to save and load a `Trie`.
+//!
+//! The format is (all integers are little endian `u32`s, strings are a length followed by utf-8 bytes):
+//! - the magic bytes `b"TRIE"` and the format `VERSION`
+//! - the `TrieConfig` (`max_suggestions`)
+//! - the suggestions table: the number of suggestions followed by the (word, score) of each
+//! - the nodes in pre-order: the index of the node's suggestion (`NONE` if absent), the number of tags
+//!   of the suggestion followed by the tags (since version 2), the number of top suggestions followed
+//!   by their indexes, the number of edges followed by the (part, node) of each
+//!
+//! The compressed edges and the top suggestions are stored as is, so loading needs no re-insertion.
+//! Only the tries without payloads can be saved. The selection boosts depend on the clock of the
+//! process, a trie with selection boosts can't be saved (see `Trie::clear_selections`).
+use crate::internal::{Edge, Node};
+use crate::tag::Tag;
+use crate::trie::TrieConfig;
+use crate::Suggestion;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"TRIE";
+const VERSION: u32 = 2;
+/// The oldest version that can be loaded, it has no tags
+const MIN_VERSION: u32 = 1;
+/// The index of a missing suggestion
+const NONE: u32 = u32::MAX;
+/// The deepest node of a snapshot, the nodes are nested as deep as the words are long (at most).
+/// The updates of a trie are recursive, so a deeper trie could overflow the stack of a thread.
+const MAX_DEPTH: usize = 1024;
+
+pub(crate) fn save(root: &Node, config: &TrieConfig, writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_u32(writer, to_u32(config.max_suggestions)?)?;
+    let mut suggestions = vec![];
+    let mut indexes = HashMap::new();
+    collect(root, &mut suggestions, &mut indexes)?;
+    write_u32(writer, to_u32(suggestions.len())?)?;
+    for suggestion in suggestions.iter() {
+        write_str(writer, &suggestion.word)?;
+        write_u32(writer, suggestion.score())?;
+    }
+    save_nodes(root, &indexes, writer)
+}
+
+pub(crate) fn load(reader: &mut impl Read) -> io::Result<(Node, TrieConfig)> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a trie snapshot"));
+    }
+    let version = read_u32(reader)?;
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(invalid_data(&format!("unsupported snapshot version {}", version)));
+    }
+    let config = TrieConfig {
+        max_suggestions: (read_u32(reader)? as usize).max(1),
+        ..TrieConfig::default()
+    };
+    let count = read_u32(reader)?;
+    let mut suggestions = vec![];
+    for _ in 0..count {
+        let word = read_str(reader)?;
+        let score = read_u32(reader)?;
+        suggestions.push(Suggestion::new(Arc::new(word), score));
+    }
+    let root = load_nodes(reader, &suggestions, version)?;
+    Ok((root, config))
+}
+
+/// Assigns an index to every suggestion (in pre-order) without recursion, the trie may be nested
+/// at most `MAX_DEPTH` deep (like the snapshots that can be loaded)
+fn collect<'a>(
+    root: &'a Node,
+    suggestions: &mut Vec<&'a Suggestion>,
+    indexes: &mut HashMap<&'a Suggestion, u32>,
+) -> io::Result<()> {
+    let mut stack = vec![(root, 1)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > MAX_DEPTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the trie is nested too deep for the snapshot format",
+            ));
+        }
+        if let Some(suggestion) = &node.suggestion {
+            if !indexes.contains_key(suggestion) {
+                indexes.insert(suggestion, suggestions.len() as u32);
+                suggestions.push(suggestion);
+            }
+        }
+        // reversed, so the first edge is popped first
+        stack.extend(node.edges.values().rev().map(|edge| (&edge.node, depth + 1)));
+    }
+    Ok(())
+}
+
+/// Saves the nodes (in pre-order) without recursion
+fn save_nodes(
+    root: &Node,
+    indexes: &HashMap<&Suggestion, u32>,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    save_node(root, indexes, writer)?;
+    let mut stack = vec![root.edges.values()];
+    while let Some(edges) = stack.last_mut() {
+        match edges.next() {
+            Some(edge) => {
+                write_str(writer, &edge.part)?;
+                save_node(&edge.node, indexes, writer)?;
+                stack.push(edge.node.edges.values());
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Saves a node without its edges (only their number)
+fn save_node(
+    node: &Node,
+    indexes: &HashMap<&Suggestion, u32>,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let index = |suggestion: &Suggestion| {
+        indexes
+            .get(suggestion)
+            .copied()
+            .ok_or_else(|| invalid_data("top suggestion is missing from the trie"))
+    };
+    match &node.suggestion {
+        Some(suggestion) => write_u32(writer, index(suggestion)?)?,
+        None => write_u32(writer, NONE)?,
+    }
+    write_u32(writer, to_u32(node.tags.len())?)?;
+    for tag in node.tags.iter() {
+        write_str(writer, tag)?;
+    }
+    write_u32(writer, to_u32(node.top_suggestions.len())?)?;
+    for suggestion in node.top_suggestions.iter() {
+        write_u32(writer, index(suggestion)?)?;
+    }
+    write_u32(writer, to_u32(node.edges.len())?)
+}
+
+/// A node being loaded: its edges still to be read and the edge (first char, part) of the child
+/// being loaded
+struct Pending {
+    node: Node,
+    edges: u32,
+    edge: Option<(char, String)>,
+}
+
+/// Loads the nodes (in pre-order) without recursion, the snapshot may be nested at most `MAX_DEPTH`
+/// deep. Returns the root.
+fn load_nodes(reader: &mut impl Read, suggestions: &[Suggestion], version: u32) -> io::Result<Node> {
+    let mut tags = HashMap::new();
+    let mut stack = vec![load_node(reader, suggestions, version, &mut tags)?];
+    while let Some(pending) = stack.last_mut() {
+        if pending.edges == 0 {
+            let mut node = stack.pop().map(|pending| pending.node).unwrap_or_else(Node::empty);
+            node.refresh_tags();
+            let parent = match stack.last_mut() {
+                Some(parent) => parent,
+                None => return Ok(node),
+            };
+            if let Some((first, part)) = parent.edge.take() {
+                parent.node.edges.insert(first, Edge::new_node(part, node));
+            }
+            continue;
+        }
+        pending.edges -= 1;
+        let part = read_str(reader)?;
+        let first = part
+            .chars()
+            .next()
+            .ok_or_else(|| invalid_data("empty edge"))?;
+        pending.edge = Some((first, part));
+        if stack.len() >= MAX_DEPTH {
+            return Err(invalid_data("the snapshot is nested too deep"));
+        }
+        stack.push(load_node(reader, suggestions, version, &mut tags)?);
+    }
+    Err(invalid_data("missing root"))
+}
+
+/// Loads a node without its edges, the `tags` are shared by the nodes
+fn load_node(
+    reader: &mut impl Read,
+    suggestions: &[Suggestion],
+    version: u32,
+    tags: &mut HashMap<String, Tag>,
+) -> io::Result<Pending> {
+    let suggestion = |index: u32| {
+        suggestions
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| invalid_data(&format!("invalid suggestion index {}", index)))
+    };
+    let mut node = Node::empty();
+    let index = read_u32(reader)?;
+    if index != NONE {
+        node.suggestion = Some(suggestion(index)?);
+    }
+    if version >= 2 {
+        for _ in 0..read_u32(reader)? {
+            let tag = read_str(reader)?;
+            let tag = tags.entry(tag).or_insert_with_key(|tag| Tag::from(&tag[..]));
+            node.tags.insert(tag.clone());
+        }
+    }
+    let top_count = read_u32(reader)?;
+    let mut top_suggestions = BTreeSet::new();
+    for _ in 0..top_count {
+        top_suggestions.insert(suggestion(read_u32(reader)?)?);
+    }
+    node.top_suggestions = top_suggestions;
+    Ok(Pending {
+        node,
+        edges: read_u32(reader)?,
+        edge: None,
+    })
+}
+
+fn to_u32(value: usize) -> io::Result<u32> {
+    if value >= NONE as usize {
+        return Err(invalid_data("the trie is too large for the snapshot format"));
+    }
+    Ok(value as u32)
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_str(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    write_u32(writer, to_u32(value.len())?)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_str(reader: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(reader)? as usize;
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated string"));
+    }
+    String::from_utf8(bytes).map_err(|e| invalid_data(&e.to_string()))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_DEPTH, NONE, VERSION};
+    use crate::normalize::StandardNormalizer;
+    use crate::tag::TagFilter;
+    use crate::trie::{Trie, TrieBuilder};
+    use std::io::ErrorKind;
+
+    fn write(bytes: &mut Vec<u8>, values: &[u32]) {
+        values.iter().for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+    }
+
+    fn data() -> Vec<(&'static str, u32)> {
+        vec![
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 3),
+            ("Café", 3),
+            ("日本語", 4),
+            ("ball", 4),
+            ("baller", 5),
+            ("", 1),
+        ]
+    }
+
+    #[test]
+    fn snapshot_round_trip_test() {
+        let trie = TrieBuilder::new().max_suggestions(3).build(&data());
+        let mut bytes = vec![];
+        trie.save(&mut bytes).unwrap();
+        let loaded = Trie::load(&bytes[..]).unwrap();
+        assert_eq!(trie, loaded);
+        assert_eq!(loaded.config().max_suggestions, 3);
+        for prefix in ["", "c", "car", "carp", "b", "日", "x"].iter() {
+            assert_eq!(trie.suggestions(prefix), loaded.suggestions(prefix));
+        }
+        // the loaded trie can still be updated
+        let mut loaded = loaded;
+        loaded.remove("cocoon");
+        loaded.upsert("cab", 9);
+        let mut expected = data();
+        expected.retain(|(w, _)| *w != "cocoon");
+        expected.push(("cab", 9));
+        assert_eq!(TrieBuilder::new().max_suggestions(3).build(&expected), loaded);
+    }
+
+    #[test]
+    fn snapshot_normalizer_test() {
+        let normalizer = StandardNormalizer::new().lowercase();
+        let trie = TrieBuilder::new().normalizer(normalizer).build(&data());
+        let mut bytes = vec![];
+        trie.save(&mut bytes).unwrap();
+        let loaded = TrieBuilder::new().normalizer(normalizer).load(&bytes[..]).unwrap();
+        assert_eq!(trie.suggestions("CAF"), loaded.suggestions("CAF"));
+        assert_eq!(loaded.suggestions("CAF").len(), 1);
+    }
+
+    #[test]
+    fn snapshot_invalid_test() {
+        let mut bytes = vec![];
+        Trie::new(&data()).save(&mut bytes).unwrap();
+        let error = Trie::load(&b"NOPE"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = Trie::load(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let mut version = bytes.clone();
+        version[4] = 3;
+        let error = Trie::load(&version[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn snapshot_tags_test() {
+        let mut trie = TrieBuilder::new().max_suggestions(2).build(&data());
+        trie.set_tags("carpet", &["sale", "new"]);
+        trie.set_tags("cain", &["new"]);
+        let mut bytes = vec![];
+        trie.save(&mut bytes).unwrap();
+        let loaded = Trie::load(&bytes[..]).unwrap();
+        assert_eq!(trie, loaded);
+        assert_eq!(loaded.tags("carpet"), Some(vec!["new", "sale"]));
+        let filter = TagFilter::tag("new");
+        assert_eq!(
+            loaded.filtered_suggestions("c", 5, &filter),
+            trie.filtered_suggestions("c", 5, &filter)
+        );
+        // the selection boosts can't be saved
+        trie.record_selection("car", "car");
+        eprintln!("after upsert");
+        let error = trie.save(&mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        trie.clear_selections();
+        assert_eq!(trie.get("car").map(|s| s.score()), Some(1));
+        trie.save(&mut vec![]).unwrap();
+    }
+
+    #[test]
+    fn snapshot_version_1_test() {
+        // "ab" (1) saved without tags
+        let mut bytes = b"TRIE".to_vec();
+        write(&mut bytes, &[1, 5, 1, 2]);
+        bytes.extend_from_slice(b"ab");
+        // the score of "ab" and the root: no suggestion, "ab" on top and one edge "ab"
+        write(&mut bytes, &[1, NONE, 1, 0, 1, 2]);
+        bytes.extend_from_slice(b"ab");
+        // the node of "ab": its suggestion on top, no edges
+        write(&mut bytes, &[0, 1, 0, 0]);
+        assert_eq!(Trie::load(&bytes[..]).unwrap(), Trie::new(&[("ab", 1)]));
+    }
+
+    #[test]
+    fn snapshot_depth_test() {
+        // a chain of nodes nested deeper than `MAX_DEPTH`
+        let mut bytes = b"TRIE".to_vec();
+        write(&mut bytes, &[VERSION, 5, 0]);
+        for _ in 0..MAX_DEPTH + 1 {
+            // no suggestion, no tags, no top suggestions, one edge "a"
+            write(&mut bytes, &[NONE, 0, 0, 1, 1]);
+            bytes.push(b'a');
+        }
+        write(&mut bytes, &[NONE, 0, 0, 0]);
+        let error = Trie::load(&bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "the snapshot is nested too deep");
+    }
+
+    #[test]
+    fn snapshot_deep_trie_test() {
+        // every word is a prefix of the next one, the node of the longest word is `MAX_DEPTH` + 1
+        // deep (with the root). The longest words are inserted first, so the inserts stay shallow.
+        let words: Vec<String> = (1..=MAX_DEPTH).rev().map(|len| "a".repeat(len)).collect();
+        let data: Vec<(&str, u32)> = words.iter().map(|w| (&w[..], w.len() as u32)).collect();
+        let trie = Trie::new(&data[1..]);
+        let mut bytes = vec![];
+        trie.save(&mut bytes).unwrap();
+        let loaded = Trie::load(&bytes[..]).unwrap();
+        assert!(trie.iter().eq(loaded.iter()));
+        assert_eq!(trie.suggestions("aaa"), loaded.suggestions("aaa"));
+        // one more word can't be loaded, so it isn't saved
+        let error = Trie::new(&data).save(&mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,120 @@
+//! The tag module.
+//! A word can be tagged (e.g. "category:city" or "sale") and the suggestions of a prefix can be
+//! restricted to the tagged words, see `Trie::set_tags` and `Trie::filtered_suggestions`.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// A tag of a word, shared by the nodes of the trie
+pub(crate) type Tag = Arc<str>;
+
+/// Restricts the suggestions to the words with (some or all of) the given tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagFilter {
+    /// The word has at least one of the tags, e.g. `tag in {sale, new}`
+    AnyOf(BTreeSet<String>),
+    /// The word has all of the tags
+    AllOf(BTreeSet<String>),
+}
+
+impl TagFilter {
+    /// The word has the given tag, e.g. `category = city` is `TagFilter::tag("category:city")`
+    pub fn tag(tag: &str) -> Self {
+        TagFilter::any_of(&[tag])
+    }
+
+    pub fn any_of(tags: &[&str]) -> Self {
+        TagFilter::AnyOf(tags.iter().map(|tag| tag.to_string()).collect())
+    }
+
+    pub fn all_of(tags: &[&str]) -> Self {
+        TagFilter::AllOf(tags.iter().map(|tag| tag.to_string()).collect())
+    }
+
+    /// Whether a word with the given `tags` passes this filter.
+    /// For the union of the tags of a subtree, this is whether any word below can pass it.
+    pub(crate) fn matches(&self, tags: &BTreeSet<Tag>) -> bool {
+        match self {
+            TagFilter::AnyOf(wanted) => wanted.iter().any(|tag| tags.contains(tag.as_str())),
+            TagFilter::AllOf(wanted) => wanted.iter().all(|tag| tags.contains(tag.as_str())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tag, TagFilter};
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{
+        as_data, assert_same, multi_script_corpus, prefixes, Rng, TaggedNaive,
+    };
+    use crate::trie::{Trie, TrieBuilder};
+    use std::collections::BTreeSet;
+
+    fn tags(tags: &[&str]) -> BTreeSet<Tag> {
+        tags.iter().map(|&tag| Tag::from(tag)).collect()
+    }
+
+    #[test]
+    fn tag_filter_test() {
+        let sale = TagFilter::any_of(&["sale", "new"]);
+        assert!(sale.matches(&tags(&["new"])));
+        assert!(sale.matches(&tags(&["sale", "category:shoe"])));
+        assert!(!sale.matches(&tags(&["category:shoe"])));
+        assert!(!sale.matches(&tags(&[])));
+        let both = TagFilter::all_of(&["sale", "new"]);
+        assert!(both.matches(&tags(&["new", "sale", "category:shoe"])));
+        assert!(!both.matches(&tags(&["new"])));
+        assert_eq!(TagFilter::tag("category:city"), TagFilter::any_of(&["category:city"]));
+        // an empty `AllOf` filter lets every word through, an empty `AnyOf` filter none
+        assert!(TagFilter::all_of(&[]).matches(&tags(&[])));
+        assert!(!TagFilter::any_of(&[]).matches(&tags(&["sale"])));
+    }
+
+    #[test]
+    fn trie_vs_naive_filtered_test() {
+        let mut rng = Rng(0x0ddb_a11c_a7e9_0123);
+        let corpus = multi_script_corpus(&mut rng, 400);
+        let data = as_data(&corpus);
+        let mut trie = TrieBuilder::new().max_suggestions(3).build(&data);
+        let mut naive = TaggedNaive::new(NaiveAutoComplete::new(&data));
+        // one in 12 words is "rare", so the filter removes most of the cached top suggestions
+        let tags = [("rare", 12), ("sale", 4), ("new", 4), ("category:city", 4)];
+        for (word, _) in corpus.iter() {
+            let tagged: Vec<&str> = tags
+                .iter()
+                .filter(|(_, odds)| rng.next(*odds) == 0)
+                .map(|(tag, _)| *tag)
+                .collect();
+            assert_eq!(trie.set_tags(word, &tagged), naive.set_tags(word, &tagged));
+        }
+        let filters = [
+            TagFilter::tag("rare"),
+            TagFilter::any_of(&["sale", "new"]),
+            TagFilter::all_of(&["sale", "category:city"]),
+        ];
+        let prefixes = prefixes(&data, 2);
+        let assert_all = |trie: &Trie, naive: &TaggedNaive| {
+            for filter in filters.iter() {
+                assert_same(
+                    &prefixes,
+                    &[1, 5],
+                    |prefix, limit| trie.filtered_suggestions(prefix, limit, filter),
+                    |prefix, limit| naive.filtered_suggestions(prefix, limit, filter),
+                    &format!("filter: {:?}", filter),
+                );
+            }
+        };
+        assert_all(&trie, &naive);
+        // the tags of the subtrees follow the updates
+        for (word, _) in corpus.iter().step_by(3) {
+            trie.set_tags(word, &["rare"]);
+            naive.set_tags(word, &["rare"]);
+        }
+        for (word, _) in corpus.iter().skip(1).step_by(3) {
+            trie.set_tags(word, &[]);
+            naive.set_tags(word, &[]);
+        }
+        assert_all(&trie, &naive);
+    }
+}
@@ -0,0 +1,314 @@
+//! The test support module. The helpers shared by the tests: a reproducible random generator, a
+//! multi-script corpus and the naive (reference) implementations of the features of the `Trie`,
+//! every suggestion is checked against the prefix.
+
+use crate::fuzzy::{self, FuzzySuggestion};
+use crate::infix::{InfixMode, InfixSuggestion};
+use crate::naive::NaiveAutoComplete;
+use crate::page::{Cursor, Page};
+use crate::phrase::{PhraseSuggestion, Tokenizer};
+use crate::rank::{self, Ranker, Signals};
+use crate::tag::{Tag, TagFilter};
+use crate::{Suggestion, DEFAULT_MAX_SUGGESTIONS};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+
+/// A small xorshift generator, so the corpus is random looking but reproducible
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+/// Returns `size` random words (and scores) of a few scripts, with combining marks and emojis
+pub(crate) fn multi_script_corpus(rng: &mut Rng, size: usize) -> Vec<(String, u32)> {
+    let scripts: [&[char]; 8] = [
+        &['c', 'a', 'r', 'é', 'e', '\u{301}', 'ü', 'ß'],
+        &['м', 'о', 'с', 'к', 'в', 'а'],
+        &['α', 'β', 'γ', 'ά', 'ω'],
+        &['日', '本', '語', '中', '文'],
+        &['한', '국', '어', '글'],
+        &['क', '्', 'ष', 'म', 'ा', 'ि'],
+        &['م', 'ر', 'ح', 'ب', 'ا'],
+        &['👍', '\u{1f3fd}', '👨', '\u{200d}', '👩', '👧'],
+    ];
+    (0..size)
+        .map(|_| {
+            let script = scripts[rng.next(scripts.len())];
+            let len = 1 + rng.next(7);
+            let word = (0..len)
+                .map(|_| {
+                    // mostly one script per word, sometimes mixed
+                    let script = if rng.next(10) == 0 {
+                        scripts[rng.next(scripts.len())]
+                    } else {
+                        script
+                    };
+                    script[rng.next(script.len())]
+                })
+                .collect::<String>();
+            (word, rng.next(20) as u32)
+        })
+        .collect()
+}
+
+/// Returns the corpus as the `(word, score)` input of a `Trie`
+pub(crate) fn as_data(corpus: &[(String, u32)]) -> Vec<(&str, u32)> {
+    corpus.iter().map(|(word, score)| (&word[..], *score)).collect()
+}
+
+/// Returns the prefixes of the words of the `data` up to `max_chars` chars (the empty one included),
+/// without duplicates
+pub(crate) fn prefixes(data: &[(&str, u32)], max_chars: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut prefixes = vec![];
+    for (word, _) in data.iter() {
+        let chars: Vec<char> = word.chars().collect();
+        for end in 0..=chars.len().min(max_chars) {
+            let prefix: String = chars[..end].iter().collect();
+            if seen.insert(prefix.clone()) {
+                prefixes.push(prefix);
+            }
+        }
+    }
+    prefixes
+}
+
+/// Asserts that the `actual` suggestions (e.g. of a `Trie`) are the `expected` ones (e.g. of the
+/// naive reference) for every prefix and every argument (e.g. a limit), the `context` is printed
+/// when they differ
+pub(crate) fn assert_same<S, A, T>(
+    prefixes: &[S],
+    args: &[A],
+    actual: impl Fn(&str, A) -> T,
+    expected: impl Fn(&str, A) -> T,
+    context: &str,
+) where
+    S: AsRef<str>,
+    A: Copy + Debug,
+    T: PartialEq + Debug,
+{
+    for prefix in prefixes.iter().map(|prefix| prefix.as_ref()) {
+        for &arg in args.iter() {
+            assert_eq!(
+                actual(prefix, arg),
+                expected(prefix, arg),
+                "\nPrefix: {:?}, argument: {:?} {}",
+                prefix,
+                arg,
+                context
+            );
+        }
+    }
+}
+
+impl NaiveAutoComplete {
+    /// Returns the top suggestions for the given `prefix`, allowing up to `max_edits` typos in the prefix.
+    /// Every suggestion is compared with the prefix, this is the reference for `Trie::fuzzy_suggestions`.
+    pub(crate) fn fuzzy_suggestions(&self, prefix: &str, max_edits: usize) -> Vec<FuzzySuggestion> {
+        let query: Vec<char> = self.normalizer.normalize(prefix).chars().collect();
+        let matches = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                let key = self.normalizer.normalize(&suggestion.word);
+                (suggestion, fuzzy::prefix_distance(&query, &key))
+            })
+            .filter(|(_, distance)| *distance <= max_edits)
+            .map(|(suggestion, distance)| FuzzySuggestion::new(suggestion.clone(), distance))
+            .collect();
+        fuzzy::rank(matches, DEFAULT_MAX_SUGGESTIONS)
+    }
+
+    /// Returns a page of the suggestions for the given `prefix` that rank below the `cursor`.
+    /// This is the reference for `Trie::suggestions_page`.
+    pub(crate) fn suggestions_page(&self, prefix: &str, cursor: Option<&Cursor>, page_size: usize) -> Page {
+        let prefix = self.normalizer.normalize(prefix);
+        let mut matching = self
+            .suggestions
+            .iter()
+            .rev()
+            .filter(|suggestion| cursor.is_none_or(|cursor| cursor.is_above(suggestion)))
+            .filter(|suggestion| {
+                self.normalizer
+                    .normalize(&suggestion.word)
+                    .starts_with(&*prefix)
+            })
+            .take(page_size + 1)
+            .cloned()
+            .collect::<Vec<Suggestion>>();
+        let more = matching.len() > page_size;
+        matching.truncate(page_size);
+        let next = match matching.last() {
+            Some(last) if more => Some(Cursor::new(last)),
+            _ => None,
+        };
+        Page {
+            suggestions: matching,
+            next,
+        }
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix` ordered by the `ranker`.
+    /// Every matching suggestion is ranked, this is the reference for `Trie::ranked_suggestions`.
+    pub(crate) fn ranked_suggestions(&self, prefix: &str, limit: usize, ranker: &dyn Ranker) -> Vec<Suggestion> {
+        let prefix = self.normalizer.normalize(prefix);
+        let signals = self
+            .words
+            .iter()
+            .filter(|(key, _)| key.starts_with(&*prefix))
+            .map(|(key, suggestion)| {
+                let prefix_len = prefix.chars().count();
+                let exact = *key == *prefix;
+                (suggestion, Signals::new(suggestion, prefix_len, key.chars().count(), exact))
+            })
+            .collect();
+        rank::rank(ranker, signals, limit)
+    }
+
+    /// Returns the top `limit` suggestions in which the given `prefix` occurs (at an offset of the
+    /// `mode`). Every suggestion is searched, this is the reference for `InfixTrie`.
+    pub(crate) fn infix_suggestions(
+        &self,
+        prefix: &str,
+        limit: usize,
+        mode: InfixMode,
+    ) -> Vec<InfixSuggestion> {
+        let prefix: Vec<char> = self.normalizer.normalize(prefix).chars().collect();
+        self.suggestions
+            .iter()
+            .rev()
+            .filter_map(|suggestion| {
+                let key: Vec<char> = self.normalizer.normalize(&suggestion.word).chars().collect();
+                let offset = mode.find(&key, &prefix)?;
+                Some(InfixSuggestion {
+                    suggestion: suggestion.clone(),
+                    offset,
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the top `limit` phrases that match the tokens of the given `prefix` (the last token
+    /// as a prefix, unless it is complete). Every phrase is tokenized, this is the reference for
+    /// `PhraseIndex`.
+    pub(crate) fn phrase_suggestions(
+        &self,
+        prefix: &str,
+        limit: usize,
+        tokenizer: &dyn Tokenizer,
+    ) -> Vec<PhraseSuggestion> {
+        let tokens = |text: &str| -> (Vec<String>, bool) {
+            let normalized = self.normalizer.normalize(text);
+            let ranges = tokenizer.tokenize(&normalized);
+            let complete = ranges.last().is_some_and(|last| last.end < normalized.len());
+            let tokens = ranges.into_iter().map(|range| normalized[range].to_string()).collect();
+            (tokens, complete)
+        };
+        let (query, complete) = tokens(prefix);
+        self.suggestions
+            .iter()
+            .rev()
+            .filter_map(|suggestion| {
+                let (phrase, _) = tokens(&suggestion.word);
+                let matches_at = |position: usize| {
+                    let rest = &phrase[position..];
+                    match query.split_last() {
+                        None => true,
+                        Some((last, earlier)) if rest.len() > earlier.len() => {
+                            let next = &rest[earlier.len()];
+                            rest[..earlier.len()] == *earlier
+                                && if complete {
+                                    next == last && rest.len() > query.len()
+                                } else {
+                                    next.starts_with(last.as_str())
+                                }
+                        }
+                        Some(_) => false,
+                    }
+                };
+                let position = (0..phrase.len().max(1)).find(|&position| matches_at(position))?;
+                Some(PhraseSuggestion {
+                    suggestion: suggestion.clone(),
+                    position,
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the words (and their scores) that start with the given `prefix`, sorted by their
+    /// (normalized) keys. This is the reference for `Trie::iter_prefix`.
+    pub(crate) fn iter_prefix(&self, prefix: &str) -> Vec<(&str, u32)> {
+        let prefix = self.normalizer.normalize(prefix);
+        let mut matching = self
+            .words
+            .iter()
+            .filter(|(key, _)| key.starts_with(&*prefix))
+            .collect::<Vec<(&String, &Suggestion)>>();
+        // utf-8 strings sort by code point, like the edges of the trie
+        matching.sort_by(|a, b| a.0.cmp(b.0));
+        matching
+            .into_iter()
+            .map(|(_, suggestion)| (&suggestion.word[..], suggestion.score))
+            .collect()
+    }
+}
+
+/// A naive auto complete with the tags of its words, this is the reference for the filtered
+/// suggestions of the `Trie`
+pub(crate) struct TaggedNaive {
+    naive: NaiveAutoComplete,
+    /// The tags by the (normalized) key
+    tags: HashMap<String, BTreeSet<Tag>>,
+}
+
+impl TaggedNaive {
+    pub(crate) fn new(naive: NaiveAutoComplete) -> Self {
+        TaggedNaive {
+            naive,
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Replaces the tags of the given `word`, returns `false` if the word is not present.
+    pub(crate) fn set_tags(&mut self, word: &str, tags: &[&str]) -> bool {
+        let key = self.naive.normalizer.normalize(word).into_owned();
+        if !self.naive.words.contains_key(&key) {
+            return false;
+        }
+        self.tags.insert(key, tags.iter().map(|&tag| Tag::from(tag)).collect());
+        true
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix` that pass the `filter`.
+    /// Every matching suggestion is checked, this is the reference for `Trie::filtered_suggestions`.
+    pub(crate) fn filtered_suggestions(
+        &self,
+        prefix: &str,
+        limit: usize,
+        filter: &TagFilter,
+    ) -> Vec<Suggestion> {
+        let naive = &self.naive;
+        let prefix = naive.normalizer.normalize(prefix);
+        let untagged = BTreeSet::new();
+        naive
+            .suggestions
+            .iter()
+            .rev()
+            .filter(|suggestion| {
+                let key = naive.normalizer.normalize(&suggestion.word);
+                key.starts_with(&*prefix)
+                    && filter.matches(self.tags.get(&*key).unwrap_or(&untagged))
+            })
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
@@ -1,33 +1,114 @@
 //! This is the trie module.
 //! This module implements trie data structure in a compressed form.
+//! The words are matched `char` by `char` (i.e. by unicode code point), a `Normalizer` can be used
+//! to match composed and decomposed forms (e.g. "é" and "e\u{301}") alike.
+//! The words are not segmented into grapheme clusters: a prefix can end inside a cluster, e.g. "👍"
+//! matches "👍🏽" (with a skin tone modifier) and "e" matches "e\u{301}" (unless it is composed).
 
 
-use crate::AutoCompletor;
+use crate::fuzzy::{self, FuzzySuggestion};
+use crate::normalize::{Identity, Normalizer};
+use crate::page::{Cursor, Page};
+use crate::rank::{self, Ranker, Signals};
+use crate::selection::{Boost, Selections};
+use crate::tag::{Tag, TagFilter};
+use crate::{flat, snapshot};
+use crate::{AutoCompletor, DEFAULT_MAX_SUGGESTIONS};
 use crate::{internal::Node, Suggestion};
 use crate::internal::{Edge};
-use std::{rc::Rc, vec::Vec};
+use std::{collections::{BTreeSet, HashMap}, io::{self, Read, Write}, sync::Arc, vec::Vec};
 use std::{
     fmt::{Debug},
+    time::{Duration, Instant},
 };
-/// The trie data structure.
-/// This is mainly used for getting auto-complete suggestions
-#[derive(Debug, PartialEq)]
-pub struct Trie {
-    root: Node,
+/// The configuration of a `Trie`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrieConfig {
+    /// The number of top suggestions cached in every node, i.e. the number of suggestions
+    /// returned by `Trie::suggestions`.
+    pub max_suggestions: usize,
+    /// Added to the score of a word when it is selected, see `Trie::record_selection`
+    pub selection_boost: u32,
+    /// Added to the score of a word for the prefix it was selected for
+    pub prefix_selection_boost: u32,
+    /// The selection boosts halve every `selection_half_life`
+    pub selection_half_life: Duration,
 }
 
-impl Trie {
+impl Default for TrieConfig {
+    fn default() -> Self {
+        TrieConfig {
+            max_suggestions: DEFAULT_MAX_SUGGESTIONS,
+            selection_boost: 1,
+            prefix_selection_boost: 1,
+            selection_half_life: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
 
-    /// Initializes the Trie from a given root `Node`
-    fn from(root: Node) -> Self {
-        Trie { root }
+/// Builds a `Trie` with a custom `TrieConfig`
+/// e.g. `TrieBuilder::new().max_suggestions(8).build(&[("car", 1), ("carpet", 2)])`
+#[derive(Debug)]
+pub struct TrieBuilder {
+    config: TrieConfig,
+    normalizer: Arc<dyn Normalizer>,
+}
+
+impl Default for TrieBuilder {
+    fn default() -> Self {
+        TrieBuilder {
+            config: TrieConfig::default(),
+            normalizer: Arc::new(Identity),
+        }
     }
+}
 
-    /// Initializes the Trie from a given list of suggestions and scores (as a tuple)
-    /// The scores are used in ordering the suggestions.
-    pub fn new(input: &[(&str, u32)]) -> Self {
-        let root = Node::empty();
-        let mut trie = Trie::from(root);
+impl TrieBuilder {
+    pub fn new() -> Self {
+        TrieBuilder::default()
+    }
+
+    /// Sets the `Normalizer` applied to the words (when inserted) and to the prefixes (when queried)
+    pub fn normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizer = Arc::new(normalizer);
+        self
+    }
+
+    /// Sets the number of top suggestions cached (and returned) per prefix (at least 1)
+    pub fn max_suggestions(mut self, max_suggestions: usize) -> Self {
+        self.config.max_suggestions = max_suggestions.max(1);
+        self
+    }
+
+    /// Sets the boosts of a selected word, globally and for the prefix it was selected for,
+    /// and how fast they decay, see `Trie::record_selection`
+    pub fn selection_boost(mut self, boost: u32, prefix_boost: u32, half_life: Duration) -> Self {
+        self.config.selection_boost = boost;
+        self.config.prefix_selection_boost = prefix_boost;
+        self.config.selection_half_life = half_life;
+        self
+    }
+
+    /// Loads a `Trie` saved with `Trie::save`, using this builder's normalizer.
+    /// The normalizer is not part of the snapshot, it must match the one used to build the saved trie.
+    /// The `max_suggestions` is read from the snapshot.
+    pub fn load(self, mut reader: impl Read) -> io::Result<Trie> {
+        let (root, saved) = snapshot::load(&mut reader)?;
+        let config = TrieConfig {
+            max_suggestions: saved.max_suggestions,
+            ..self.config
+        };
+        Ok(Trie::from(root, config, self.normalizer))
+    }
+
+    /// Builds an empty `Trie`, e.g. the index of an `InfixTrie`
+    pub(crate) fn build_empty<P>(self) -> Trie<P> {
+        Trie::from(Node::empty(), self.config, self.normalizer)
+    }
+
+    /// Builds the `Trie` from a given list of suggestions and scores (as a tuple)
+    pub fn build(self, input: &[(&str, u32)]) -> Trie {
+        let mut trie = self.build_empty();
         input
             .iter()
             .for_each(|(input, score)| {
@@ -36,32 +117,291 @@ impl Trie {
         trie
     }
 
+    /// Builds the `Trie` from a given list of suggestions, scores and payloads
+    /// e.g. `TrieBuilder::new().build_with_payloads(vec![("paris", 3, City { id: 1 })])`
+    pub fn build_with_payloads<'a, P>(
+        self,
+        input: impl IntoIterator<Item = (&'a str, u32, P)>,
+    ) -> Trie<P> {
+        let mut trie = self.build_empty();
+        input.into_iter().for_each(|(word, score, payload)| {
+            trie.upsert_with_payload(word, score, payload);
+        });
+        trie
+    }
+}
+
+/// The trie data structure.
+/// This is mainly used for getting auto-complete suggestions
+/// Every word can carry a payload of type `P` (see `Suggestion::payload`), which is returned
+/// with its suggestions. Only the tries without a payload can be saved.
+#[derive(Debug)]
+pub struct Trie<P = ()> {
+    root: Node<P>,
+    config: TrieConfig,
+    normalizer: Arc<dyn Normalizer>,
+    selections: Selections,
+}
 
+impl<P> PartialEq for Trie<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root && self.config == other.config
+    }
+}
+
+impl Trie {
+    /// Initializes the Trie from a given list of suggestions and scores (as a tuple)
+    /// The scores are used in ordering the suggestions.
+    pub fn new(input: &[(&str, u32)]) -> Self {
+        TrieBuilder::new().build(input)
+    }
+
+    /// Inserts (or replaces) the given `word` with the `score`, sharing the word with the caller.
+    pub(crate) fn upsert_shared(&mut self, word: Arc<String>, score: u32) -> Option<Suggestion> {
+        let str = self.key(&word);
+        self.upsert_at(&str, Suggestion::new(word, score))
+    }
+
+    /// Writes a binary snapshot of this `Trie`, which can be read back with `Trie::load`.
+    /// The snapshot keeps the compressed edges, the top suggestions and the tags of every node.
+    /// The selection boosts are not saved, a trie with boosts fails with `ErrorKind::InvalidInput`
+    /// (see `clear_selections`), so does a trie nested more than 1024 nodes deep (which can't be
+    /// loaded).
+    pub fn save(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.check_no_selections()?;
+        snapshot::save(&self.root, &self.config, writer)
+    }
+
+    /// Writes the read-only flat layout of this `Trie`, which can be queried in place (e.g. memory mapped)
+    /// with a `FlatTrie`. The layout is streamed to the `writer` (it is not buffered in memory).
+    /// Like `save`, a trie with selection boosts fails with `ErrorKind::InvalidInput`.
+    pub fn save_flat(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.check_no_selections()?;
+        flat::save(&self.root, &self.config, writer)
+    }
+
+    /// The selection boosts depend on the clock of the process, so they can't be saved
+    fn check_no_selections(&self) -> io::Result<()> {
+        if !self.selections.words.is_empty() || !self.selections.prefixes.is_empty() {
+            let message = "the selection boosts can't be saved, clear them first";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        Ok(())
+    }
+
+    /// Loads a `Trie` from a snapshot written by `Trie::save` (without re-inserting the suggestions).
+    /// The snapshot is read in small chunks, so files should be wrapped in a `BufReader`.
+    /// See `TrieBuilder::load` for tries with a custom normalizer.
+    pub fn load(reader: impl Read) -> io::Result<Trie> {
+        TrieBuilder::new().load(reader)
+    }
+}
+
+impl<P: Default> Trie<P> {
     /// inserts the given (suggestion, score) tuple into the `Trie`
+    /// If the suggestion is already present, its score is replaced (see `upsert`).
     pub fn add_suggestion(&mut self, suggestion: (String, u32)) {
-        let str: Vec<char> = suggestion.0.chars().collect();
-        let suggestion = Rc::new(Suggestion::new(Rc::new(suggestion.0), suggestion.1));
-        Trie::insert_at(&mut self.root, &str, suggestion);
+        self.upsert(&suggestion.0, suggestion.1);
     }
 
-    fn insert_at(node: &mut Node, remaining: &[char], suggestion: Rc<Suggestion>) {
-        node.add_suggestion(suggestion.clone());
-        if remaining.len() == 0 {
+    /// Inserts the given `word` with the `score`, or replaces the score if the word is already present.
+    /// Returns the previous suggestion (if any).
+    /// The payload of a present word is kept, a new word gets the default payload.
+    pub fn upsert(&mut self, word: &str, score: u32) -> Option<Suggestion<P>> {
+        let str = self.key(word);
+        let payload = match Trie::find(&self.root, &str) {
+            Some(current) => current.payload,
+            None => Arc::new(P::default()),
+        };
+        self.upsert_at(&str, Suggestion::with_payload(Arc::new(word.into()), score, payload))
+    }
+}
+
+impl<P> Trie<P> {
+    /// Initializes the Trie from a given root `Node`
+    fn from(root: Node<P>, config: TrieConfig, normalizer: Arc<dyn Normalizer>) -> Self {
+        Trie {
+            root,
+            config,
+            normalizer,
+            selections: Selections::default(),
+        }
+    }
+
+    /// Returns the configuration of this `Trie`
+    pub fn config(&self) -> &TrieConfig {
+        &self.config
+    }
+
+    /// Returns an empty `Trie` with the configuration and the normalizer of this one
+    pub(crate) fn empty_like<Q>(&self) -> Trie<Q> {
+        Trie::from(Node::empty(), self.config, self.normalizer.clone())
+    }
+
+    /// Returns the (normalized) characters used to match the given word or prefix
+    pub(crate) fn key(&self, word: &str) -> Vec<char> {
+        self.normalizer.normalize(word).chars().collect()
+    }
+
+    pub(crate) fn normalizer(&self) -> &dyn Normalizer {
+        self.normalizer.as_ref()
+    }
+
+    /// Inserts the given `word` with the `score` and the `payload`, or replaces both if the word
+    /// is already present. Returns the previous suggestion (if any).
+    pub fn upsert_with_payload(
+        &mut self,
+        word: &str,
+        score: u32,
+        payload: P,
+    ) -> Option<Suggestion<P>> {
+        let str = self.key(word);
+        let suggestion = Suggestion::with_payload(Arc::new(word.into()), score, Arc::new(payload));
+        self.upsert_at(&str, suggestion)
+    }
+
+    /// Inserts (or replaces) the `suggestion` at the given (normalized) key,
+    /// which need not be the key of its word (see `InfixTrie`)
+    pub(crate) fn upsert_at(
+        &mut self,
+        str: &[char],
+        suggestion: Suggestion<P>,
+    ) -> Option<Suggestion<P>> {
+        // the score of a selected word keeps its selection boost
+        let suggestion = match self.selections.words.get(str) {
+            Some((_, applied)) => suggestion.with_score(suggestion.score.saturating_add(*applied)),
+            None => suggestion,
+        };
+        let max = self.config.max_suggestions;
+        let previous = Trie::replace_at(&mut self.root, str, suggestion.clone(), max);
+        if previous.is_none() {
+            Trie::insert_at(&mut self.root, str, suggestion, max);
+        }
+        previous
+    }
+
+    /// Increments the score of the given `word` by `delta` (saturating at `u32::MAX`).
+    /// Returns the updated suggestion, or `None` if the word is not present.
+    pub fn increment_score(&mut self, word: &str, delta: u32) -> Option<Suggestion<P>> {
+        let str = self.key(word);
+        let current = Trie::find(&self.root, &str)?;
+        let suggestion = current.with_score(current.score.saturating_add(delta));
+        Trie::replace_at(&mut self.root, &str, suggestion.clone(), self.config.max_suggestions);
+        Some(suggestion)
+    }
+
+    /// Returns the suggestion of exactly the given `word` (if it is present)
+    pub fn get(&self, word: &str) -> Option<Suggestion<P>> {
+        Trie::find(&self.root, &self.key(word))
+    }
+
+    /// Returns the suggestion stored at exactly the given (normalized) key (if any), see `upsert_at`
+    pub(crate) fn get_key(&self, str: &[char]) -> Option<Suggestion<P>> {
+        Trie::find(&self.root, str)
+    }
+
+    /// Returns the suggestion stored for exactly the given `remaining` characters (if any).
+    fn find(node: &Node<P>, remaining: &[char]) -> Option<Suggestion<P>> {
+        Trie::find_node(node, remaining)?.suggestion.clone()
+    }
+
+    /// Returns the node at exactly the given `remaining` characters (if any).
+    fn find_node<'a>(node: &'a Node<P>, remaining: &[char]) -> Option<&'a Node<P>> {
+        if remaining.is_empty() {
+            return Some(node);
+        }
+        let edge = node.edges.get(&remaining[0])?;
+        let part_chars: Vec<char> = edge.part.chars().collect();
+        if !remaining.starts_with(&part_chars) {
+            return None;
+        }
+        Trie::find_node(&edge.node, &remaining[part_chars.len()..])
+    }
+
+    /// Replaces the tags of the given `word`, e.g. `["category:city"]`.
+    /// Returns `false` (and changes nothing) if the word is not present.
+    /// The tags are kept when the score is updated and dropped when the word is removed.
+    /// They are kept by `save` and `load`, but not by `save_flat` (a `FlatTrie` has no tags).
+    pub fn set_tags(&mut self, word: &str, tags: &[&str]) -> bool {
+        let str = self.key(word);
+        let tags = tags.iter().map(|&tag| Tag::from(tag)).collect();
+        Trie::set_tags_at(&mut self.root, &str, tags)
+    }
+
+    fn set_tags_at(node: &mut Node<P>, remaining: &[char], tags: BTreeSet<Tag>) -> bool {
+        if remaining.is_empty() {
+            if node.suggestion.is_none() {
+                return false;
+            }
+            node.tags = tags;
+            node.refresh_tags();
+            return true;
+        }
+        let edge = match node.edges.get_mut(&remaining[0]) {
+            Some(edge) => edge,
+            None => return false,
+        };
+        let part_chars: Vec<char> = edge.part.chars().collect();
+        if !remaining.starts_with(&part_chars)
+            || !Trie::set_tags_at(&mut edge.node, &remaining[part_chars.len()..], tags)
+        {
+            return false;
+        }
+        node.refresh_tags();
+        true
+    }
+
+    /// Returns the tags of the given `word`, or `None` if the word is not present.
+    pub fn tags(&self, word: &str) -> Option<Vec<&str>> {
+        let node = Trie::find_node(&self.root, &self.key(word))?;
+        node.suggestion.as_ref()?;
+        Some(node.tags.iter().map(|tag| &tag[..]).collect())
+    }
+
+    /// Replaces an existing suggestion and recomputes the top suggestions along the path.
+    /// Returns the replaced suggestion, nothing is changed if the word is not present.
+    fn replace_at(
+        node: &mut Node<P>,
+        remaining: &[char],
+        suggestion: Suggestion<P>,
+        max: usize,
+    ) -> Option<Suggestion<P>> {
+        if remaining.is_empty() {
+            node.suggestion.as_ref()?;
+            let previous = node.suggestion.replace(suggestion);
+            node.refresh_top_suggestions(max);
+            return previous;
+        }
+        let edge = node.edges.get_mut(&remaining[0])?;
+        let part_chars: Vec<char> = edge.part.chars().collect();
+        if !remaining.starts_with(&part_chars) {
+            return None;
+        }
+        let remaining = &remaining[part_chars.len()..];
+        let previous = Trie::replace_at(&mut edge.node, remaining, suggestion, max)?;
+        node.refresh_top_suggestions(max);
+        Some(previous)
+    }
+
+    fn insert_at(node: &mut Node<P>, remaining: &[char], suggestion: Suggestion<P>, max: usize) {
+        node.add_suggestion(suggestion.clone(), max);
+        if remaining.is_empty() {
+            node.suggestion = Some(suggestion);
             return;
         }
         let ch = remaining[0];
         if let Some(mut edge) = node.edges.remove(&ch) {
             let mut part_index: usize = 0;
-            let part = &edge.part;
-            let part_chars: Vec<char> = part.chars().collect();
+            // all the indexes are char (not byte) indexes, e.g. "é" is 1 char but 2 bytes
+            let part_chars: Vec<char> = edge.part.chars().collect();
             // advance until they match
             // e.g. "car" (part) & "can" (remaining), will result in
             // matching till "ca"
-            while part_index < part.len()
+            while part_index < part_chars.len()
                 && part_index < remaining.len()
                 && part_chars[part_index] == remaining[part_index]
             {
-                part_index = part_index + 1;
+                part_index += 1;
             }
             //"ca"
             let prefix = &part_chars[..part_index];
@@ -74,69 +414,442 @@ impl Trie {
                 // create a temporary edge for the common prefix ("ca")
                 let mut temp_edge = Edge::empty(prefix.iter().collect());
                 temp_edge.node.top_suggestions = edge.node.top_suggestions.clone();
+                temp_edge.node.subtree_tags = edge.node.subtree_tags.clone();
                 // insert the suffix of the part ("r")
                 temp_edge.node.edges.insert(
-                    suffix_of_part[0].into(),
+                    suffix_of_part[0],
                     Edge::new_node(suffix_of_part.iter().collect(), edge.node),
                 );
                 // insert the suffix on the remaining input ("n")
-                Trie::insert_at(&mut temp_edge.node, suffix_of_remaining, suggestion);
+                Trie::insert_at(&mut temp_edge.node, suffix_of_remaining, suggestion, max);
                 // insert the temporary edge back into the trie
-                node.edges.insert(prefix[0].into(), temp_edge);
+                node.edges.insert(prefix[0], temp_edge);
             } else {
-                Trie::insert_at(&mut edge.node, suffix_of_remaining, suggestion);
+                Trie::insert_at(&mut edge.node, suffix_of_remaining, suggestion, max);
                 node.edges.insert(ch, edge);
             }
         } else {
             node.edges
-                .insert(ch, Edge::new(remaining.iter().collect(), suggestion));
+                .insert(ch, Edge::new(remaining.iter().collect(), suggestion, max));
+        }
+    }
+
+    /// Removes the given `word` from the `Trie` and returns its suggestion (if it was present).
+    /// The top suggestions along the path are recomputed and an edge left with a single child
+    /// is merged with that child, so the trie stays compressed.
+    pub fn remove(&mut self, word: &str) -> Option<Suggestion<P>> {
+        let str = self.key(word);
+        self.remove_key(&str)
+    }
+
+    /// Removes the suggestion at the given (normalized) key, see `upsert_at`
+    pub(crate) fn remove_key(&mut self, str: &[char]) -> Option<Suggestion<P>> {
+        let removed = Trie::remove_at(&mut self.root, str, self.config.max_suggestions)?;
+        self.selections.remove(str);
+        Some(removed)
+    }
+
+    /// Records that the `word` was selected (picked from the suggestions) for the `prefix` now.
+    /// The score of the word is boosted by `TrieConfig::selection_boost` (in place, i.e. the word
+    /// rises in the suggestions of all its prefixes), and its score for the `prefix` is boosted by
+    /// `TrieConfig::prefix_selection_boost` (see `suggestions`). The boosts decay over time, the
+    /// suggestions are scored with the boosts decayed to the query time (see `suggestions_at`).
+    /// Returns the boosted suggestion, or `None` if the word is not present.
+    /// A trie with selection boosts can't be saved, see `save`.
+    pub fn record_selection(&mut self, prefix: &str, word: &str) -> Option<Suggestion<P>> {
+        self.record_selection_at(prefix, word, Instant::now())
+    }
+
+    /// Records that the `word` was selected for the `prefix` at the given instant
+    pub fn record_selection_at(
+        &mut self,
+        prefix: &str,
+        word: &str,
+        at: Instant,
+    ) -> Option<Suggestion<P>> {
+        let key = self.key(word);
+        let current = Trie::find(&self.root, &key)?;
+        let half_life = self.config.selection_half_life;
+        let (boost, applied) = self
+            .selections
+            .words
+            .entry(key.clone())
+            .or_insert((Boost::new(at), 0));
+        boost.add(self.config.selection_boost, half_life, at);
+        let base = current.score.saturating_sub(*applied);
+        *applied = boost.score_at(half_life, at);
+        let suggestion = current.with_score(base.saturating_add(*applied));
+        Trie::replace_at(&mut self.root, &key, suggestion.clone(), self.config.max_suggestions);
+        let prefix = self.key(prefix);
+        if key.starts_with(&prefix) && self.config.prefix_selection_boost > 0 {
+            self.selections
+                .prefixes
+                .entry(prefix)
+                .or_default()
+                .entry(key)
+                .or_insert_with(|| Boost::new(at))
+                .add(self.config.prefix_selection_boost, half_life, at);
+        }
+        Some(suggestion)
+    }
+
+    /// Decays the selection boosts to their value now, updating the cached scores in place.
+    /// The suggestions are always scored with the decayed boosts, but every boosted word under a
+    /// prefix is rescored at query time: decaying the boosts (e.g. every hour) forgets the boosts
+    /// that faded away and keeps the queries cheap.
+    pub fn decay_selections(&mut self) {
+        self.decay_selections_at(Instant::now());
+    }
+
+    /// Decays the selection boosts to their value at the instant `now`, the boosts that
+    /// decayed to nothing are dropped.
+    pub fn decay_selections_at(&mut self, now: Instant) {
+        let half_life = self.config.selection_half_life;
+        let max = self.config.max_suggestions;
+        for (key, (boost, applied)) in self.selections.words.iter_mut() {
+            let decayed = boost.score_at(half_life, now);
+            if decayed == *applied {
+                continue;
+            }
+            if let Some(current) = Trie::find(&self.root, key) {
+                let base = current.score.saturating_sub(*applied);
+                let suggestion = current.with_score(base.saturating_add(decayed));
+                Trie::replace_at(&mut self.root, key, suggestion, max);
+            }
+            *applied = decayed;
+        }
+        self.selections.words.retain(|_, (_, applied)| *applied > 0);
+        self.selections.prefixes.retain(|_, boosts| {
+            boosts.retain(|_, boost| boost.score_at(half_life, now) > 0);
+            !boosts.is_empty()
+        });
+    }
+
+    /// Drops all the selection boosts, the words get their scores back
+    pub fn clear_selections(&mut self) {
+        let max = self.config.max_suggestions;
+        for (key, (_, applied)) in std::mem::take(&mut self.selections.words) {
+            if let Some(current) = Trie::find(&self.root, &key) {
+                let suggestion = current.with_score(current.score.saturating_sub(applied));
+                Trie::replace_at(&mut self.root, &key, suggestion, max);
+            }
+        }
+        self.selections.prefixes.clear();
+    }
+
+    fn remove_at(node: &mut Node<P>, remaining: &[char], max: usize) -> Option<Suggestion<P>> {
+        if remaining.is_empty() {
+            let removed = node.suggestion.take()?;
+            node.tags.clear();
+            node.refresh_top_suggestions(max);
+            node.refresh_tags();
+            return Some(removed);
+        }
+        let ch = remaining[0];
+        let edge = node.edges.get_mut(&ch)?;
+        let part_chars: Vec<char> = edge.part.chars().collect();
+        if !remaining.starts_with(&part_chars) {
+            // the word ends within (or diverges from) this edge, i.e. it is not in the trie
+            return None;
+        }
+        let removed = Trie::remove_at(&mut edge.node, &remaining[part_chars.len()..], max)?;
+        if edge.node.suggestion.is_none() {
+            if edge.node.edges.is_empty() {
+                // nothing left below this edge
+                node.edges.remove(&ch);
+            } else if edge.node.edges.len() == 1 {
+                // e.g. removing "carpe" from ["carpe", "carpet"] leaves "carp" -> "e" -> "t",
+                // which is merged back to "carp" -> "et"
+                let (_, child) = std::mem::take(&mut edge.node.edges).into_iter().next().unwrap();
+                edge.part.push_str(&child.part);
+                edge.node = child.node;
+            }
         }
+        node.refresh_top_suggestions(max);
+        node.refresh_tags();
+        Some(removed)
     }
 
     /// Returns the top suggestions for the give `prefix`
-    pub fn suggestions(&self, prefix: &str) -> Vec<Rc<Suggestion>> {
-        let inp: Vec<char> = prefix.chars().collect();
-        Trie::walk(&self.root, &inp)
+    /// The selected words are boosted, see `record_selection`.
+    pub fn suggestions(&self, prefix: &str) -> Vec<Suggestion<P>> {
+        self.suggestions_at(prefix, self.config.max_suggestions, Instant::now())
     }
 
-    fn walk(node: &Node, input: &[char]) -> Vec<Rc<Suggestion>> {
-        if input.len() == 0 {
-            return node.sorted_suggestions();
-        } else {
-            if let Some(edge) = node.edges.get(&input[0]) {
-                let mut index = 0;
-                let part: Vec<char> = edge.part.chars().collect();
-                while index < input.len() && index < part.len() && input[index] == part[index] {
-                    index = index + 1
-                }
-                if index == part.len() {
-                    // exhausted all characters in part, we continue walking
-                    return Trie::walk(&edge.node, &input[index..]);
-                } else if index == input.len() {
-                    // exhausted all characters in input, we return this node's suggestion
-                    return edge.node.sorted_suggestions();
-                } else {
-                    // there is a mismatch, no suggestions found.
-                    return vec![];
+    /// Returns the top `limit` suggestions for the given `prefix`.
+    /// If `limit` is larger than the configured `max_suggestions`, the subtree of the prefix
+    /// is walked to fill in the rest.
+    pub fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion<P>> {
+        self.suggestions_at(prefix, limit, Instant::now())
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix`, with the selection boosts
+    /// (see `record_selection`) decayed to the instant `now`
+    pub fn suggestions_at(&self, prefix: &str, limit: usize, now: Instant) -> Vec<Suggestion<P>> {
+        let inp = self.key(prefix);
+        let half_life = self.config.selection_half_life;
+        // the cached scores of the selected words hold their boosts at their last update
+        let mut rescored: HashMap<Vec<char>, Suggestion<P>> = HashMap::new();
+        for (key, (boost, applied)) in self.selections.words_with_prefix(&inp) {
+            if let Some(suggestion) = Trie::find(&self.root, key) {
+                let base = suggestion.score.saturating_sub(*applied);
+                let score = base.saturating_add(boost.score_at(half_life, now));
+                rescored.insert(key.clone(), suggestion.with_score(score));
+            }
+        }
+        for (key, boost) in self.selections.prefixes.get(&inp).into_iter().flatten() {
+            let suggestion = match rescored.remove(key) {
+                Some(suggestion) => suggestion,
+                None => match Trie::find(&self.root, key) {
+                    Some(suggestion) => suggestion,
+                    None => continue,
+                },
+            };
+            let score = suggestion.score.saturating_add(boost.score_at(half_life, now));
+            rescored.insert(key.clone(), suggestion.with_score(score));
+        }
+        if rescored.is_empty() {
+            return self.top_suggestions(&inp, limit);
+        }
+        // the current scores are at most the cached ones, so the top `limit` of the other words
+        // are among the cached top `limit + rescored.len()`
+        let others: Vec<Suggestion<P>> = self
+            .top_suggestions(&inp, limit.saturating_add(rescored.len()))
+            .into_iter()
+            .filter(|suggestion| !rescored.contains_key(&self.key(&suggestion.word)))
+            .collect();
+        let mut suggestions: Vec<Suggestion<P>> = rescored.into_values().chain(others).collect();
+        suggestions.sort_by(|a, b| b.cmp(a));
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix` ordered by the `ranker`.
+    /// The `ranker.candidates(limit)` best suggestions by score (and the prefix itself, if it is a word)
+    /// are re-ranked, i.e. a suggestion outside of them is not returned.
+    pub fn ranked_suggestions(
+        &self,
+        prefix: &str,
+        limit: usize,
+        ranker: &dyn Ranker,
+    ) -> Vec<Suggestion<P>> {
+        let inp = self.key(prefix);
+        let mut candidates = self.top_suggestions(&inp, ranker.candidates(limit));
+        if let Some(exact) = Trie::find(&self.root, &inp) {
+            if !candidates.contains(&exact) {
+                candidates.push(exact);
+            }
+        }
+        let keys: Vec<Vec<char>> = candidates.iter().map(|s| self.key(&s.word)).collect();
+        let signals = candidates
+            .iter()
+            .zip(keys.iter())
+            .map(|(suggestion, key)| {
+                (suggestion, Signals::new(suggestion, inp.len(), key.len(), *key == inp))
+            })
+            .collect();
+        rank::rank(ranker, signals, limit)
+    }
+
+    /// Returns the top `limit` suggestions for the given (normalized) `inp`
+    pub(crate) fn top_suggestions(&self, inp: &[char], limit: usize) -> Vec<Suggestion<P>> {
+        let node = match Trie::walk(&self.root, inp) {
+            Some(node) => node,
+            None => return vec![],
+        };
+        if limit <= self.config.max_suggestions {
+            return node.sorted_suggestions().into_iter().take(limit).collect();
+        }
+        let mut collected = BTreeSet::new();
+        node.collect_suggestions(limit, &mut collected);
+        collected.into_iter().rev().collect()
+    }
+
+    /// Returns a page of (at most) `page_size` suggestions for the given `prefix` in score order,
+    /// starting after the `cursor` (or at the best suggestion without a cursor).
+    /// The pages go beyond the cached top suggestions, e.g. a "show more" list.
+    pub fn suggestions_page(
+        &self,
+        prefix: &str,
+        cursor: Option<&Cursor>,
+        page_size: usize,
+    ) -> Page<P> {
+        let inp = self.key(prefix);
+        let (suggestions, more) = match Trie::walk(&self.root, &inp) {
+            Some(node) => node.page(cursor, page_size, self.config.max_suggestions, None),
+            None => (vec![], false),
+        };
+        let next = match suggestions.last() {
+            Some(last) if more => Some(Cursor::new(last)),
+            _ => None,
+        };
+        Page { suggestions, next }
+    }
+
+    /// Returns the top `limit` suggestions for the given `prefix` that pass the `filter`.
+    /// The subtree of the prefix is searched (best first) beyond the cached top suggestions,
+    /// skipping the branches without the tags of the filter.
+    pub fn filtered_suggestions(
+        &self,
+        prefix: &str,
+        limit: usize,
+        filter: &TagFilter,
+    ) -> Vec<Suggestion<P>> {
+        match Trie::walk(&self.root, &self.key(prefix)) {
+            Some(node) => node.page(None, limit, self.config.max_suggestions, Some(filter)).0,
+            None => vec![],
+        }
+    }
+
+    /// Returns an iterator over all the words (and their scores) in the lexicographic order
+    /// of their (normalized) keys.
+    pub fn iter(&self) -> Iter<'_, P> {
+        Iter::new(Some(&self.root))
+    }
+
+    /// Returns an iterator over the words (and their scores) that start with the given `prefix`,
+    /// in the lexicographic order of their (normalized) keys.
+    pub fn iter_prefix(&self, prefix: &str) -> Iter<'_, P> {
+        Iter::new(Trie::walk(&self.root, &self.key(prefix)))
+    }
+
+    /// Returns the top suggestions for the given `prefix`, allowing up to `max_edits` typos
+    /// (insertions, deletions or substitutions) in the prefix.
+    /// The suggestions are ranked by edit distance first and score second.
+    pub fn fuzzy_suggestions(&self, prefix: &str, max_edits: usize) -> Vec<FuzzySuggestion<P>> {
+        let query = self.key(prefix);
+        let row = fuzzy::first_row(&query);
+        let best = row[query.len()];
+        let limit = self.config.max_suggestions;
+        let mut matches = vec![];
+        Trie::fuzzy_walk(&self.root, &query, &row, best, max_edits, limit, &mut matches);
+        fuzzy::rank(matches, limit)
+    }
+
+    /// Walks the trie (character by character) maintaining a row of the Levenshtein matrix,
+    /// `best` is the smallest distance between the query and the prefixes walked so far.
+    fn fuzzy_walk(
+        node: &Node<P>,
+        query: &[char],
+        row: &[usize],
+        best: usize,
+        max_edits: usize,
+        limit: usize,
+        matches: &mut Vec<FuzzySuggestion<P>>,
+    ) {
+        if let Some(suggestion) = &node.suggestion {
+            if best <= max_edits {
+                matches.push(FuzzySuggestion::new(suggestion.clone(), best));
+            }
+        }
+        for edge in node.edges.values() {
+            let mut row = row.to_vec();
+            let mut best = best;
+            let mut done = false;
+            for ch in edge.part.chars() {
+                row = fuzzy::next_row(&row, query, ch);
+                best = best.min(row[query.len()]);
+                // the distance can not improve anymore, i.e. an exact match
+                // or every extension of this path is already beyond max_edits
+                if best == 0 || row.iter().min() > Some(&max_edits) {
+                    done = true;
+                    break;
                 }
             }
-            return vec![];
+            if !done {
+                Trie::fuzzy_walk(&edge.node, query, &row, best, max_edits, limit, matches);
+            } else if best <= max_edits {
+                // every suggestion below this edge matches with the same distance
+                let mut collected = BTreeSet::new();
+                edge.node.collect_suggestions(limit, &mut collected);
+                matches.extend(
+                    collected
+                        .into_iter()
+                        .map(|suggestion| FuzzySuggestion::new(suggestion, best)),
+                );
+            }
         }
     }
+
+    /// Returns the node that holds the suggestions for the given `input` (if any)
+    fn walk<'a>(node: &'a Node<P>, input: &[char]) -> Option<&'a Node<P>> {
+        if input.is_empty() {
+            return Some(node);
+        }
+        if let Some(edge) = node.edges.get(&input[0]) {
+            let mut index = 0;
+            let part: Vec<char> = edge.part.chars().collect();
+            while index < input.len() && index < part.len() && input[index] == part[index] {
+                index += 1
+            }
+            if index == part.len() {
+                // exhausted all characters in part, we continue walking
+                return Trie::walk(&edge.node, &input[index..]);
+            } else if index == input.len() {
+                // exhausted all characters in input, we return this node
+                return Some(&edge.node);
+            } else {
+                // there is a mismatch, no suggestions found.
+                return None;
+            }
+        }
+        None
+    }
+}
+
+/// A lazy iterator over the (word, score) entries of a `Trie`, see `Trie::iter` and `Trie::iter_prefix`
+pub struct Iter<'a, P = ()> {
+    /// The nodes left to visit, the next one is at the end
+    stack: Vec<&'a Node<P>>,
+}
+
+impl<'a, P> Iter<'a, P> {
+    fn new(node: Option<&'a Node<P>>) -> Self {
+        Iter {
+            stack: node.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a, P> Iterator for Iter<'a, P> {
+    type Item = (&'a str, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // pre-order, i.e. a word comes before the longer words it is a prefix of
+        while let Some(node) = self.stack.pop() {
+            self.stack
+                .extend(node.edges.values().rev().map(|edge| &edge.node));
+            if let Some(suggestion) = &node.suggestion {
+                return Some((&suggestion.word, suggestion.score));
+            }
+        }
+        None
+    }
 }
 
 impl AutoCompletor for Trie {
-    fn suggestions(&self, prefix: &str) -> Vec<Rc<Suggestion>> { 
+    fn suggestions(&self, prefix: &str) -> Vec<Suggestion> { 
         self.suggestions(prefix)
     }
+
+    fn suggestions_with_limit(&self, prefix: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggestions_with_limit(prefix, limit)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
-    use std::rc::Rc;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
-    use super::{Edge, Node, Suggestion, Trie};
+    use super::{Cursor, Edge, Identity, Node, Page, Suggestion, Trie, TrieBuilder, TrieConfig};
+    use crate::rank::{Ranker, StaticRanker, WeightedRanker};
+    use crate::tag::TagFilter;
+    use crate::naive::NaiveAutoComplete;
+    use crate::test_support::{as_data, assert_same, multi_script_corpus, prefixes, Rng};
+    use crate::DEFAULT_MAX_SUGGESTIONS;
+    use crate::normalize::{StandardNormalizer, UnicodeForm};
 
     #[test]
     fn trie_structural_test() {
@@ -151,16 +864,16 @@ mod tests {
         let edges = &mut carpe.edges;
 
         let carpet = new_edge(edges, 't', "t", "carpet", 2);
-        carpet.suggestion = Some(Rc::new(Suggestion::new(Rc::new("carpet".into()), 2)));
+        carpet.suggestion = Some(Suggestion::new(Arc::new("carpet".into()), 2));
         carpet.with_top_suggestions(vec![("carpet", 2)]);
 
         let carpenter = new_edge(edges, 'n', "nter", "carpenter", 3);
-        carpenter.suggestion = Some(Rc::new(Suggestion::new(Rc::new("carpenter".into()), 3)));
+        carpenter.suggestion = Some(Suggestion::new(Arc::new("carpenter".into()), 3));
         carpenter.with_top_suggestions(vec![("carpenter", 3)]);
 
         root.with_top_suggestions(vec![("car", 1), ("carpet", 2), ("carpenter", 3)]);
 
-        let expected = Trie::from(root);
+        let expected = Trie::from(root, TrieConfig::default(), Arc::new(Identity));
         assert_eq!(dbg!(expected), dbg!(actual));
     }
 
@@ -189,6 +902,402 @@ mod tests {
         assert_suggestions("", vec!["cocoon", "baller", "ball", "carpenter", "cameo"], &trie);
     }
 
+    #[test]
+    fn trie_remove_test() {
+        let data = [
+            ("car", 1),
+            ("carpe", 4),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 3),
+            ("cameo", 3),
+            ("ball", 4),
+            ("baller", 5),
+            ("", 1),
+        ];
+        for &(word, score) in data.iter() {
+            let mut actual = Trie::new(&data);
+            let removed = actual.remove(word);
+            assert_eq!(removed, Some(Suggestion::new(Arc::new(word.into()), score)));
+            let remaining: Vec<(&str, u32)> = data
+                .iter()
+                .filter(|(w, _)| *w != word)
+                .cloned()
+                .collect();
+            // removing a word must leave the trie exactly as if it was never inserted
+            assert_eq!(Trie::new(&remaining), actual, "\nRemoved: '{}'", word);
+        }
+    }
+
+    #[test]
+    fn trie_remove_missing_test() {
+        let data = [("car", 1), ("carpet", 2), ("carpenter", 3)];
+        let mut trie = Trie::new(&data);
+        assert_eq!(trie.remove("carp"), None);
+        assert_eq!(trie.remove("carpets"), None);
+        assert_eq!(trie.remove("cab"), None);
+        assert_eq!(trie.remove(""), None);
+        assert_eq!(Trie::new(&data), trie);
+        trie.remove("car");
+        trie.remove("carpet");
+        trie.remove("carpenter");
+        assert_eq!(Trie::new(&[]), trie);
+        assert_suggestions("c", vec![], &trie);
+    }
+
+    #[test]
+    fn trie_upsert_test() {
+        let mut trie = Trie::new(&[("car", 1), ("carpet", 2), ("carpenter", 3), ("carpet", 4)]);
+        assert_eq!(Trie::new(&[("car", 1), ("carpet", 4), ("carpenter", 3)]), trie);
+        assert_suggestions("car", vec!["carpet", "carpenter", "car"], &trie);
+
+        let previous = trie.upsert("carpenter", 5);
+        assert_eq!(previous, Some(Suggestion::new(Arc::new("carpenter".into()), 3)));
+        assert_eq!(Trie::new(&[("car", 1), ("carpet", 4), ("carpenter", 5)]), trie);
+
+        let updated = trie.increment_score("car", 9);
+        assert_eq!(updated, Some(Suggestion::new(Arc::new("car".into()), 10)));
+        assert_eq!(trie.increment_score("carp", 9), None);
+        assert_eq!(Trie::new(&[("car", 10), ("carpet", 4), ("carpenter", 5)]), trie);
+        assert_suggestions("car", vec!["car", "carpenter", "carpet"], &trie);
+    }
+
+    #[test]
+    fn trie_max_suggestions_test() {
+        let trie = TrieBuilder::new().max_suggestions(2).build(&[
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+        ]);
+        assert_eq!(trie.config().max_suggestions, 2);
+        assert_suggestions("c", vec!["cocoon", "carpenter"], &trie);
+        assert_suggestions("car", vec!["carpenter", "carpet"], &trie);
+        let words: Vec<String> = trie
+            .suggestions_with_limit("car", 8)
+            .iter()
+            .map(|s| (*s.word).clone())
+            .collect();
+        assert_eq!(words, vec!["carpenter", "carpet", "car"]);
+        // at least one suggestion is cached
+        let trie = TrieBuilder::new().max_suggestions(0).build(&[("car", 1), ("carpet", 2)]);
+        assert_eq!(trie.config().max_suggestions, 1);
+        assert_suggestions("car", vec!["carpet"], &trie);
+        assert_eq!(trie.suggestions_with_limit("car", 2).len(), 2);
+    }
+
+    #[test]
+    fn trie_fuzzy_suggestions_test() {
+        let trie = Trie::new(&[
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 3),
+            ("ball", 4),
+            ("baller", 5),
+        ]);
+        let fuzzy = |prefix: &str, max_edits: usize| -> Vec<(String, usize)> {
+            trie.fuzzy_suggestions(prefix, max_edits)
+                .iter()
+                .map(|f| ((*f.suggestion.word).clone(), f.distance))
+                .collect()
+        };
+        assert_eq!(fuzzy("carpnt", 0), vec![]);
+        assert_eq!(
+            fuzzy("carpnt", 1),
+            vec![("carpenter".into(), 1), ("carpet".into(), 1)]
+        );
+        assert_eq!(fuzzy("crapet", 2), vec![("carpet".into(), 2)]);
+        assert_eq!(
+            fuzzy("vall", 1),
+            vec![("baller".into(), 1), ("ball".into(), 1)]
+        );
+        assert_eq!(
+            fuzzy("car", 0),
+            vec![("carpenter".into(), 0), ("carpet".into(), 0), ("car".into(), 0)]
+        );
+    }
+
+    #[test]
+    fn trie_normalizer_test() {
+        let normalizer = StandardNormalizer::new().lowercase().form(UnicodeForm::Nfc);
+        let mut trie = TrieBuilder::new().normalizer(normalizer).build(&[
+            ("Car", 1),
+            ("carpet", 2),
+            ("Cafe\u{301}", 3),
+        ]);
+        assert_suggestions("car", vec!["carpet", "Car"], &trie);
+        assert_suggestions("CA", vec!["Cafe\u{301}", "carpet", "Car"], &trie);
+        assert_suggestions("caf\u{e9}", vec!["Cafe\u{301}"], &trie);
+        // the display form is replaced by the latest upsert of the same key
+        trie.upsert("CAFÉ", 4);
+        assert_suggestions("café", vec!["CAFÉ"], &trie);
+        assert_eq!(trie.remove("cAr").map(|s| (*s.word).clone()), Some("Car".into()));
+        assert_suggestions("c", vec!["CAFÉ", "carpet"], &trie);
+    }
+
+    #[test]
+    fn trie_multi_byte_test() {
+        let trie = Trie::new(&[
+            ("é", 1),
+            ("éa", 2),
+            ("éé", 3),
+            ("日本", 4),
+            ("日本語", 5),
+            ("👍🏽", 6),
+            ("👍", 7),
+        ]);
+        assert_suggestions("é", vec!["éé", "éa", "é"], &trie);
+        assert_suggestions("éé", vec!["éé"], &trie);
+        assert_suggestions("日", vec!["日本語", "日本"], &trie);
+        assert_suggestions("日本語", vec!["日本語"], &trie);
+        assert_suggestions("日語", vec![], &trie);
+        // a limitation (see the module docs): the edges are split on chars, not on grapheme
+        // clusters, so a prefix that ends inside a cluster matches it
+        assert_suggestions("👍", vec!["👍", "👍🏽"], &trie);
+        let trie = Trie::new(&[("e\u{301}t\u{e9}", 1), ("et", 2)]);
+        assert_suggestions("e", vec!["et", "e\u{301}t\u{e9}"], &trie);
+    }
+
+    #[test]
+    fn trie_iter_test() {
+        let mut trie = Trie::new(&[
+            ("carpet", 2),
+            ("car", 1),
+            ("cocoon", 5),
+            ("carpenter", 3),
+            ("cain", 2),
+            ("", 4),
+            ("日本", 4),
+        ]);
+        assert_eq!(
+            trie.iter().collect::<Vec<(&str, u32)>>(),
+            vec![
+                ("", 4),
+                ("cain", 2),
+                ("car", 1),
+                ("carpenter", 3),
+                ("carpet", 2),
+                ("cocoon", 5),
+                ("日本", 4)
+            ]
+        );
+        assert_eq!(
+            trie.iter_prefix("carp").collect::<Vec<(&str, u32)>>(),
+            vec![("carpenter", 3), ("carpet", 2)]
+        );
+        // the prefix ends within an edge
+        assert_eq!(trie.iter_prefix("coc").collect::<Vec<(&str, u32)>>(), vec![("cocoon", 5)]);
+        assert_eq!(trie.iter_prefix("cx").next(), None);
+        assert_eq!(trie.iter_prefix("c").take(2).count(), 2);
+        trie.remove("car");
+        trie.upsert("ca", 7);
+        assert_eq!(
+            trie.iter_prefix("ca").collect::<Vec<(&str, u32)>>(),
+            vec![("ca", 7), ("cain", 2), ("carpenter", 3), ("carpet", 2)]
+        );
+    }
+
+    #[test]
+    fn trie_suggestions_page_test() {
+        let trie = TrieBuilder::new().max_suggestions(2).build(&[
+            ("car", 1),
+            ("carpet", 2),
+            ("carpenter", 3),
+            ("cocoon", 5),
+            ("cain", 4),
+            ("cab", 6),
+            ("dog", 9),
+        ]);
+        let words = |page: &Page| -> Vec<String> {
+            page.suggestions.iter().map(|s| (*s.word).clone()).collect()
+        };
+        let first = trie.suggestions_page("c", None, 3);
+        assert_eq!(words(&first), vec!["cab", "cocoon", "cain"]);
+        let second = trie.suggestions_page("c", first.next.as_ref(), 3);
+        assert_eq!(words(&second), vec!["carpenter", "carpet", "car"]);
+        assert_eq!(second.next, None);
+        // the cursor survives a round trip through a string
+        let cursor = first.next.unwrap().to_string().parse::<Cursor>().unwrap();
+        assert_eq!(trie.suggestions_page("c", Some(&cursor), 3), second);
+        let page = trie.suggestions_page("carp", None, 5);
+        assert_eq!(words(&page), vec!["carpenter", "carpet"]);
+        assert_eq!(page.next, None);
+        assert_eq!(trie.suggestions_page("x", None, 5).suggestions, vec![]);
+        assert_eq!(trie.suggestions_page("c", None, 0).suggestions, vec![]);
+    }
+
+    #[test]
+    fn trie_ranked_suggestions_test() {
+        let trie = TrieBuilder::new().max_suggestions(2).build(&[
+            ("car", 1),
+            ("carpet", 4),
+            ("carpenter", 5),
+            ("cart", 4),
+            ("cocoon", 5),
+        ]);
+        let words = |prefix: &str, ranker: &dyn Ranker| -> Vec<String> {
+            trie.ranked_suggestions(prefix, 2, ranker)
+                .iter()
+                .map(|s| (*s.word).clone())
+                .collect()
+        };
+        assert_eq!(words("car", &StaticRanker), vec!["carpenter", "cart"]);
+        // the exact match is a candidate, even though it is not in the top (by score)
+        let exact = WeightedRanker::new().exact_match_boost(10.0);
+        assert_eq!(words("car", &exact), vec!["car", "carpenter"]);
+        assert_eq!(words("carp", &exact), vec!["carpenter", "carpet"]);
+        let short = WeightedRanker::new().length_penalty(1.0);
+        assert_eq!(words("car", &short), vec!["cart", "carpet"]);
+        assert_eq!(words("x", &short), Vec::<String>::new());
+    }
+
+    #[test]
+    fn trie_payload_test() {
+        #[derive(Debug, Default, PartialEq)]
+        struct Place {
+            id: u32,
+            category: &'static str,
+        }
+        let place = |id, category| Place { id, category };
+        let mut trie = TrieBuilder::new().build_with_payloads(vec![
+            ("paris", 3, place(1, "city")),
+            ("park", 2, place(2, "amenity")),
+            ("parking", 1, place(3, "amenity")),
+        ]);
+        let payloads = |trie: &Trie<Place>, prefix: &str| -> Vec<u32> {
+            trie.suggestions(prefix).iter().map(|s| s.payload().id).collect()
+        };
+        assert_eq!(payloads(&trie, "par"), vec![1, 2, 3]);
+        assert_eq!(trie.suggestions("pari")[0].payload(), &place(1, "city"));
+        // the payload is shared by the top suggestions of every prefix, not copied
+        let (a, b) = (&trie.suggestions("p")[0], &trie.suggestions("paris")[0]);
+        assert!(Arc::ptr_eq(&a.payload, &b.payload));
+        // the payload is kept when the score changes
+        trie.upsert("parking", 5);
+        trie.increment_score("park", 1);
+        // "park" (3) ranks above "paris" (3)
+        assert_eq!(payloads(&trie, "par"), vec![3, 2, 1]);
+        trie.upsert_with_payload("park", 9, place(4, "amenity"));
+        assert_eq!(payloads(&trie, "par"), vec![4, 3, 1]);
+        // a new word gets the default payload
+        trie.upsert("pa", 0);
+        assert_eq!(trie.suggestions("pa")[3].payload(), &Place::default());
+        assert_eq!(trie.remove("paris").map(|s| s.payload().id), Some(1));
+        assert_eq!(trie.iter_prefix("par").count(), 2);
+        assert_eq!(trie.fuzzy_suggestions("prk", 1)[0].suggestion.payload().id, 4);
+    }
+
+    #[test]
+    fn trie_filtered_suggestions_test() {
+        let mut trie = TrieBuilder::new().max_suggestions(2).build(&[
+            ("cairo", 1),
+            ("camera", 9),
+            ("canvas", 8),
+            ("cap", 7),
+            ("car", 6),
+            ("caracas", 2),
+            ("cardigan", 5),
+        ]);
+        trie.set_tags("cairo", &["category:city"]);
+        trie.set_tags("caracas", &["category:city"]);
+        trie.set_tags("cardigan", &["sale", "new"]);
+        trie.set_tags("cap", &["new"]);
+        assert!(!trie.set_tags("ca", &["sale"]));
+        assert!(!trie.set_tags("carpet", &["sale"]));
+        let words = |trie: &Trie, prefix: &str, filter: &TagFilter| -> Vec<String> {
+            trie.filtered_suggestions(prefix, 5, filter)
+                .iter()
+                .map(|s| (*s.word).clone())
+                .collect()
+        };
+        // none of the cached top suggestions ("camera", "canvas") is a city
+        let city = TagFilter::tag("category:city");
+        assert_eq!(words(&trie, "ca", &city), vec!["caracas", "cairo"]);
+        assert_eq!(words(&trie, "car", &city), vec!["caracas"]);
+        let any = TagFilter::any_of(&["sale", "new"]);
+        assert_eq!(words(&trie, "ca", &any), vec!["cap", "cardigan"]);
+        assert_eq!(words(&trie, "ca", &TagFilter::all_of(&["sale", "new"])), vec!["cardigan"]);
+        assert_eq!(words(&trie, "cam", &city), Vec::<String>::new());
+        assert_eq!(words(&trie, "x", &city), Vec::<String>::new());
+        assert_eq!(trie.tags("cardigan"), Some(vec!["new", "sale"]));
+        assert_eq!(trie.tags("camera"), Some(vec![]));
+        assert_eq!(trie.tags("ca"), None);
+        // the tags are kept on upsert, dropped on remove and survive the compression of the trie
+        trie.upsert("cairo", 3);
+        assert_eq!(words(&trie, "ca", &city), vec!["cairo", "caracas"]);
+        trie.upsert("carpet", 4);
+        trie.remove("car");
+        trie.remove("carpet");
+        assert_eq!(words(&trie, "car", &any), vec!["cardigan"]);
+        trie.remove("caracas");
+        trie.upsert("caracas", 2);
+        assert_eq!(words(&trie, "ca", &city), vec!["cairo"]);
+    }
+
+    #[test]
+    fn trie_record_selection_test() {
+        let mut trie = TrieBuilder::new()
+            .max_suggestions(2)
+            .selection_boost(2, 3, Duration::from_secs(60 * 60))
+            .build(&[("car", 1), ("cart", 5), ("carpet", 4), ("cat", 3)]);
+        let words = |trie: &Trie, prefix: &str| -> Vec<(String, u32)> {
+            trie.suggestions(prefix)
+                .iter()
+                .map(|s| ((*s.word).clone(), s.score()))
+                .collect()
+        };
+        let owned = |words: &[(&str, u32)]| -> Vec<(String, u32)> {
+            words.iter().map(|(w, s)| (w.to_string(), *s)).collect()
+        };
+        let start = Instant::now();
+        let car = trie.record_selection_at("car", "car", start);
+        assert_eq!(car.map(|s| s.score()), Some(3));
+        assert_eq!(words(&trie, "ca"), owned(&[("cart", 5), ("carpet", 4)]));
+        // the prefix boost only applies to the prefix the word was selected for
+        assert_eq!(words(&trie, "car"), owned(&[("car", 6), ("cart", 5)]));
+        assert_eq!(trie.record_selection_at("car", "car", start).map(|s| s.score()), Some(5));
+        // the cached top suggestions along the path are updated in place
+        assert_eq!(words(&trie, "ca"), owned(&[("cart", 5), ("car", 5)]));
+        assert_eq!(words(&trie, "car"), owned(&[("car", 11), ("cart", 5)]));
+        let top = trie.suggestions_with_limit("car", 1);
+        assert_eq!((&top[0].word[..], top[0].score(), top.len()), ("car", 11, 1));
+        assert_eq!(trie.record_selection_at("ca", "carpet", start).map(|s| s.score()), Some(6));
+        assert_eq!(trie.record_selection_at("x", "cat", start).map(|s| s.score()), Some(5));
+        assert_eq!(words(&trie, "x"), vec![]);
+        assert_eq!(trie.record_selection_at("car", "carb", start), None);
+        // the boost is kept when the score is updated
+        trie.upsert("car", 2);
+        assert_eq!(trie.get("car").map(|s| s.score()), Some(6));
+        // the boosts halve every hour, the suggestions are scored with the boosts decayed to the
+        // query time
+        let later = |trie: &Trie, prefix: &str, hours: u64| -> Vec<(String, u32)> {
+            let now = start + Duration::from_secs(hours * 60 * 60);
+            trie.suggestions_at(prefix, 2, now)
+                .iter()
+                .map(|s| ((*s.word).clone(), s.score()))
+                .collect()
+        };
+        assert_eq!(later(&trie, "car", 1), owned(&[("car", 7), ("cart", 5)]));
+        assert_eq!(later(&trie, "ca", 1), owned(&[("carpet", 7), ("cart", 5)]));
+        assert_eq!(later(&trie, "car", 10), owned(&[("cart", 5), ("carpet", 4)]));
+        assert_eq!(later(&trie, "ca", 10), owned(&[("cart", 5), ("carpet", 4)]));
+        trie.decay_selections_at(start + Duration::from_secs(60 * 60));
+        assert_eq!(trie.get("car").map(|s| s.score()), Some(4));
+        assert_eq!(trie.get("cat").map(|s| s.score()), Some(4));
+        trie.decay_selections_at(start + Duration::from_secs(10 * 60 * 60));
+        assert_eq!(words(&trie, "car"), owned(&[("cart", 5), ("carpet", 4)]));
+        assert_eq!(words(&trie, "ca"), owned(&[("cart", 5), ("carpet", 4)]));
+        assert_eq!(trie.get("car").map(|s| s.score()), Some(2));
+        // the boosts are dropped with the word
+        trie.record_selection_at("car", "car", start);
+        trie.remove("car");
+        trie.upsert("car", 1);
+        assert_eq!(words(&trie, "car"), owned(&[("cart", 5), ("carpet", 4)]));
+    }
+
     fn assert_suggestions(prefix: &str, expected: Vec<&str>, trie: &Trie) {
         let actual: Vec<String> = trie
             .suggestions(prefix)
@@ -204,7 +1313,7 @@ mod tests {
     }
 
     fn new_edge<'a>(
-        edges: &'a mut HashMap<char, Edge>,
+        edges: &'a mut BTreeMap<char, Edge>,
         c: char,
         part: &'a str,
         suggestion: &str,
@@ -214,12 +1323,31 @@ mod tests {
             .entry(c)
             .or_insert(Edge::new(
                 part.into(),
-                Rc::new(Suggestion::new(Rc::new(suggestion.into()), score)),
+                Suggestion::new(Arc::new(suggestion.into()), score),
+                DEFAULT_MAX_SUGGESTIONS,
             ))
             .node
     }
 
-    fn empty_edge<'a>(edges: &'a mut HashMap<char, Edge>, c: char, part: &'a str) -> &'a mut Node {
+    fn empty_edge<'a>(edges: &'a mut BTreeMap<char, Edge>, c: char, part: &'a str) -> &'a mut Node {
         &mut edges.entry(c).or_insert(Edge::empty(part.into())).node
     }
+
+    #[test]
+    fn trie_vs_naive_iter_test() {
+        let mut rng = Rng(0x0dd_ba11_cafe_f00d);
+        let corpus = multi_script_corpus(&mut rng, 500);
+        let data = as_data(&corpus);
+        let normalizer = StandardNormalizer::new().lowercase().form(UnicodeForm::Nfc);
+        let trie = TrieBuilder::new().normalizer(normalizer).build(&data);
+        let naive = NaiveAutoComplete::with_normalizer(&data, normalizer);
+        assert_eq!(trie.iter().collect::<Vec<_>>(), naive.iter_prefix(""));
+        assert_same(
+            &prefixes(&data, usize::MAX),
+            &[()],
+            |prefix, _| trie.iter_prefix(prefix).collect::<Vec<_>>(),
+            |prefix, _| naive.iter_prefix(prefix),
+            "",
+        );
+    }
 }
@@ -0,0 +1,204 @@
+//! The completion server. It serves the suggestions of an `AutoCompletor` over HTTP:
+//! `GET /complete?q=<prefix>&k=<n>` returns
+//! `{"prefix":"<prefix>","suggestions":[{"word":"<word>","score":<score>},...]}`
+use completion::loader::{Format, Loader};
+use completion::{trie::Trie, AutoCompletor, DEFAULT_MAX_SUGGESTIONS};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The largest `k` accepted by `/complete`
+pub const MAX_K: usize = 100;
+
+/// Loads a dictionary into a `Trie`, see `parse_dictionary` for the format.
+pub fn load_dictionary(path: impl AsRef<Path>) -> io::Result<Trie> {
+    let entries = parse_dictionary(BufReader::new(File::open(path)?))?;
+    let input = entries
+        .iter()
+        .map(|(word, score)| (&word[..], *score))
+        .collect::<Vec<(&str, u32)>>();
+    Ok(Trie::new(&input))
+}
+
+/// Parses a dictionary with one `<word>\t<score>` per line, empty lines are skipped.
+/// The score is after the last tab (the word may contain tabs), the words are trimmed.
+/// A malformed line fails the whole dictionary (with its line number).
+pub fn parse_dictionary(reader: impl BufRead) -> io::Result<Vec<(String, u32)>> {
+    Ok(Loader::new(Format::Tsv).score_last().load(reader)?)
+}
+
+/// An HTTP server answering completion requests from a shared `AutoCompletor`
+pub struct CompletionServer {
+    server: Arc<Server>,
+    completor: Arc<dyn AutoCompletor>,
+}
+
+impl CompletionServer {
+    /// Binds the server to the given address (e.g. "0.0.0.0:8080", port 0 picks a free port)
+    pub fn bind(addr: impl ToSocketAddrs, completor: Arc<dyn AutoCompletor>) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        Ok(CompletionServer {
+            server: Arc::new(server),
+            completor,
+        })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Serves the requests with `workers` threads, blocks forever.
+    pub fn run(self, workers: usize) {
+        let handles = (0..workers.max(1))
+            .map(|_| self.spawn_worker())
+            .collect::<Vec<JoinHandle<()>>>();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+
+    /// Serves the requests in a background thread, e.g. for tests.
+    pub fn spawn(self) -> JoinHandle<()> {
+        self.spawn_worker()
+    }
+
+    fn spawn_worker(&self) -> JoinHandle<()> {
+        let server = self.server.clone();
+        let completor = self.completor.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                respond(completor.as_ref(), request);
+            }
+        })
+    }
+}
+
+fn respond(completor: &dyn AutoCompletor, request: Request) {
+    let (status, body) = if *request.method() != Method::Get {
+        (405, json!({ "error": "method not allowed" }).to_string())
+    } else {
+        handle(completor, request.url())
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type);
+    // the client may have gone away, there is nobody to report the error to
+    let _ = request.respond(response);
+}
+
+/// Handles a GET request for the given `url`, returns the status code and the JSON body
+pub fn handle(completor: &dyn AutoCompletor, url: &str) -> (u16, String) {
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => (url, ""),
+    };
+    if path != "/complete" {
+        return (404, json!({ "error": "not found" }).to_string());
+    }
+    let mut prefix = None;
+    let mut k = DEFAULT_MAX_SUGGESTIONS;
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "q" => prefix = Some(value.into_owned()),
+            "k" => match value.parse::<usize>() {
+                Ok(value) if value <= MAX_K => k = value,
+                _ => {
+                    let error = format!("k must be a number between 0 and {}", MAX_K);
+                    return (400, json!({ "error": error }).to_string());
+                }
+            },
+            _ => {}
+        }
+    }
+    let prefix = match prefix {
+        Some(prefix) => prefix,
+        None => return (400, json!({ "error": "missing parameter q" }).to_string()),
+    };
+    let suggestions = completor
+        .suggestions_with_limit(&prefix, k)
+        .iter()
+        .map(|s| json!({ "word": &*s.word, "score": s.score() }))
+        .collect::<Vec<_>>();
+    let body = json!({ "prefix": prefix, "suggestions": suggestions });
+    (200, body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle, parse_dictionary, CompletionServer};
+    use completion::trie::Trie;
+    use serde_json::{json, Value};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn trie() -> Trie {
+        Trie::new(&[("car", 1), ("carpet", 2), ("carpenter", 3), ("cocoon", 5), ("new york", 4)])
+    }
+
+    fn get(trie: &Trie, url: &str) -> (u16, Value) {
+        let (status, body) = handle(trie, url);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn handle_test() {
+        let trie = trie();
+        let (status, body) = get(&trie, "/complete?q=car&k=2");
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            json!({
+                "prefix": "car",
+                "suggestions": [{"word": "carpenter", "score": 3}, {"word": "carpet", "score": 2}]
+            })
+        );
+        let (_, body) = get(&trie, "/complete?q=new%20y");
+        assert_eq!(body["suggestions"][0]["word"], "new york");
+        let (_, body) = get(&trie, "/complete?q=c");
+        assert_eq!(body["suggestions"].as_array().unwrap().len(), 4);
+        assert_eq!(get(&trie, "/complete?q=xyz").1["suggestions"], json!([]));
+        assert_eq!(get(&trie, "/complete").0, 400);
+        assert_eq!(get(&trie, "/complete?q=c&k=many").0, 400);
+        assert_eq!(get(&trie, "/complete?q=c&k=1000").0, 400);
+        assert_eq!(get(&trie, "/other?q=c").0, 404);
+    }
+
+    #[test]
+    fn parse_dictionary_test() {
+        let entries = parse_dictionary(&b"car\t1\nnew york\t 4\n\ncarpet\t2\n"[..]).unwrap();
+        assert_eq!(
+            entries,
+            vec![("car".into(), 1), ("new york".into(), 4), ("carpet".into(), 2)]
+        );
+        let error = parse_dictionary(&b"car\t1\ncarpet\n"[..]).unwrap_err();
+        assert!(error.to_string().starts_with("line 2:"), "{}", error);
+        let error = parse_dictionary(&b"car\t-1\n"[..]).unwrap_err();
+        assert!(error.to_string().starts_with("line 1:"), "{}", error);
+        let entries = parse_dictionary(&b"word\twith\ttabs\t5\n carpet \t2\n"[..]).unwrap();
+        assert_eq!(entries, vec![("word\twith\ttabs".into(), 5), ("carpet".into(), 2)]);
+    }
+
+    #[test]
+    fn server_test() {
+        let server = CompletionServer::bind("127.0.0.1:0", Arc::new(trie())).unwrap();
+        let addr = server.local_addr().unwrap();
+        server.spawn();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET /complete?q=carp&k=1 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("application/json"), "{}", response);
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["suggestions"], json!([{"word": "carpenter", "score": 3}]));
+    }
+}
@@ -1,3 +1,48 @@
+use server::{load_dictionary, CompletionServer};
+use std::{env, process::exit, sync::Arc, time::Instant};
+
+const DEFAULT_PORT: u16 = 8080;
+const WORKERS: usize = 4;
+
+fn usage() -> ! {
+    eprintln!("Usage: server --dictionary <path> [--port <port>]");
+    eprintln!("The dictionary has one <word>\\t<score> per line");
+    exit(-1);
+}
+
 fn main() {
-    println!("Hello, world server");
+    let mut dictionary = None;
+    let mut port = DEFAULT_PORT;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dictionary" => dictionary = Some(args.next().unwrap_or_else(|| usage())),
+            "--port" => {
+                port = match args.next().map(|p| p.parse::<u16>()) {
+                    Some(Ok(port)) => port,
+                    _ => usage(),
+                }
+            }
+            _ => usage(),
+        }
+    }
+    let dictionary = dictionary.unwrap_or_else(|| usage());
+    let instant = Instant::now();
+    let trie = match load_dictionary(&dictionary) {
+        Ok(trie) => trie,
+        Err(e) => {
+            eprintln!("Could not load the dictionary '{}': {}", dictionary, e);
+            exit(-1);
+        }
+    };
+    println!("Loaded '{}' in {:?}", dictionary, instant.elapsed());
+    let server = match CompletionServer::bind(("0.0.0.0", port), Arc::new(trie)) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Could not listen on port {}: {}", port, e);
+            exit(-1);
+        }
+    };
+    println!("Listening on http://0.0.0.0:{}/complete?q=<prefix>&k=<n>", port);
+    server.run(WORKERS);
 }